@@ -0,0 +1,147 @@
+use kinode_process_lib::vfs::{create_file, open_file};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+// files bigger than this aren't indexed.
+pub const MAX_INDEXED_SIZE: u64 = 1048576;
+// tokens outside this length are noise: initials, hashes, base64.
+const MIN_TOKEN_LEN: usize = 2;
+const MAX_TOKEN_LEN: usize = 64;
+
+// extensions we read as text. anything else only if its MIME type says it's text.
+const TEXT_EXTENSIONS: [&str; 24] = [
+    "txt", "md", "markdown", "rst", "org", "csv", "tsv", "json", "toml", "yaml", "yml", "xml",
+    "html", "css", "js", "ts", "tsx", "jsx", "rs", "py", "go", "c", "h", "sh",
+];
+
+/// inverted index of the words in our text files.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ContentIndex {
+    // token -> the files it appears in.
+    terms: HashMap<String, BTreeSet<String>>,
+    // file -> its tokens, so it can be dropped or reindexed.
+    files: HashMap<String, BTreeSet<String>>,
+}
+
+impl ContentIndex {
+    pub fn load(path: &str) -> Self {
+        open_file(path, false, None)
+            .ok()
+            .and_then(|file| file.read().ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+        create_file(path, None)?.write(&serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    pub fn add(&mut self, path: &str, text: &str) {
+        self.remove(path);
+        let tokens = tokenize(text);
+        for token in &tokens {
+            self.terms
+                .entry(token.clone())
+                .or_default()
+                .insert(path.to_string());
+        }
+        self.files.insert(path.to_string(), tokens);
+    }
+
+    pub fn remove(&mut self, path: &str) {
+        let Some(tokens) = self.files.remove(path) else {
+            return;
+        };
+        for token in tokens {
+            if let Some(paths) = self.terms.get_mut(&token) {
+                paths.remove(path);
+                if paths.is_empty() {
+                    self.terms.remove(&token);
+                }
+            }
+        }
+    }
+
+    /// files containing every word of query, sorted.
+    pub fn query(&self, query: &str) -> Vec<String> {
+        let mut tokens = tokenize(query).into_iter();
+        let Some(first) = tokens.next() else {
+            return vec![];
+        };
+        let mut hits = self.terms.get(&first).cloned().unwrap_or_default();
+        for token in tokens {
+            let Some(paths) = self.terms.get(&token) else {
+                return vec![];
+            };
+            hits.retain(|path| paths.contains(path));
+        }
+        hits.into_iter().collect()
+    }
+}
+
+pub fn tokenize(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| (MIN_TOKEN_LEN..=MAX_TOKEN_LEN).contains(&word.chars().count()))
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+pub fn is_text(path: &str) -> bool {
+    let extension = path.rsplit_once('.').map_or("", |(_, extension)| extension).to_lowercase();
+    TEXT_EXTENSIONS.contains(&extension.as_str())
+        || mime_guess::from_path(path)
+            .first_raw()
+            .is_some_and(|mime| mime.starts_with("text/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> ContentIndex {
+        let mut index = ContentIndex::default();
+        index.add("a.md", "The quick brown fox");
+        index.add("b.md", "a quick red fox");
+        index.add("c.md", "lazy dogs");
+        index
+    }
+
+    #[test]
+    fn query_needs_every_word() {
+        let index = index();
+        assert_eq!(index.query("fox"), vec!["a.md", "b.md"]);
+        assert_eq!(index.query("quick FOX"), vec!["a.md", "b.md"]);
+        assert_eq!(index.query("brown fox"), vec!["a.md"]);
+        assert!(index.query("brown dogs").is_empty());
+        assert!(index.query("cat").is_empty());
+        assert!(index.query("").is_empty());
+    }
+
+    #[test]
+    fn remove_drops_a_file_and_its_words() {
+        let mut index = index();
+        index.remove("a.md");
+        assert_eq!(index.query("fox"), vec!["b.md"]);
+        assert!(!index.terms.contains_key("brown"));
+        assert!(!index.files.contains_key("a.md"));
+        // removing what isn't there leaves the rest alone.
+        index.remove("a.md");
+        assert_eq!(index.query("fox"), vec!["b.md"]);
+    }
+
+    #[test]
+    fn adding_again_replaces_the_old_words() {
+        let mut index = index();
+        index.add("a.md", "slow green turtle");
+        assert_eq!(index.query("fox"), vec!["b.md"]);
+        assert_eq!(index.query("turtle"), vec!["a.md"]);
+        assert!(!index.terms.contains_key("brown"));
+    }
+
+    #[test]
+    fn tokenize_skips_noise() {
+        let tokens = tokenize("A b-Cd, ef! cd");
+        assert_eq!(tokens.into_iter().collect::<Vec<_>>(), vec!["cd", "ef"]);
+    }
+}
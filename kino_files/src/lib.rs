@@ -3,13 +3,18 @@ use kinode_process_lib::{
         bind_http_path, bind_ws_path, send_response, send_ws_push, serve_ui, HttpServerRequest,
         StatusCode, WsMessageType,
     }, our_capabilities, print_to_terminal, println, set_state, spawn, vfs::{
-        create_drive, create_file, metadata, open_dir, open_file, remove_dir, remove_file, Directory, FileType
+        create_drive, create_file, metadata, open_dir, open_file, remove_dir, remove_file, DirEntry, Directory, FileType
     }, Address, LazyLoadBlob, Message, OnExit, ProcessId, Request, Response
 };
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::io::{Cursor, Read};
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod index;
+use index::ContentIndex;
 
 wit_bindgen::generate!({
     path: "target/wit",
@@ -17,6 +22,30 @@ wit_bindgen::generate!({
 });
 
 const ICON: &str = include_str!("icon");
+// hidden folder in the files drive where workers keep transfer bookkeeping.
+const TRANSFERS_DIR: &str = ".transfers";
+// cached thumbnails of our images, hidden from listings like transfers.
+const THUMBNAILS_DIR: &str = ".thumbnails";
+// images bigger than this get no thumbnail, decoding them would take too long.
+const MAX_THUMBNAIL_SOURCE_SIZE: u64 = 20971520;
+// image formats we can decode.
+const THUMBNAIL_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "webp", "bmp"];
+// the content index lives in its own drive next to the files drive.
+const INDEX_DRIVE: &str = "index";
+const INDEX_FILE: &str = "content.json";
+// most entries a single ListPage carries, counting everything under its folders.
+const MAX_LIST_PAGE: usize = 500;
+// transfer history lives in a drive of its own too, one json entry per line, oldest first.
+const HISTORY_DRIVE: &str = "history";
+const HISTORY_FILE: &str = "history.jsonl";
+// how long a swarm download waits to hear what it's downloading, in seconds.
+const SWARM_HASH_TIMEOUT: u64 = 600;
+// most workers hashing files for FileHash requests at once.
+const MAX_HASH_WORKERS: usize = 4;
+// most offers we keep in our inbox from any one node.
+const MAX_OFFERS_PER_NODE: usize = 20;
+// seconds an offer of ours lasts if it isn't used up before then.
+const OFFER_EXPIRY: u64 = 604800;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NodePermission {
@@ -26,9 +55,99 @@ pub struct NodePermission {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum KinoRequest {
+    // everything we may see, in one go.
     ListFiles,
-    Download { name: String, target: Address },
-    Progress { name: String, progress: u64 },
+    // a page of it, see ListOptions.
+    ListPage(ListOptions),
+    Download {
+        name: String,
+        target: Address,
+        #[serde(default)]
+        resume: bool,
+        // set when fetching by content, the serving node may find it under another path.
+        #[serde(default)]
+        content: Option<ContentId>,
+        // where to put it in our files drive, relative to it. defaults to the file name.
+        #[serde(default)]
+        save_as: Option<String>,
+        // folder in our files drive to put it in, and what to do if something's already there.
+        #[serde(default)]
+        dest: Option<String>,
+        #[serde(default)]
+        on_collision: CollisionPolicy,
+    },
+    // download every file we're allowed to see under directory `name` on `target`,
+    // recreating the folder structure under our files drive.
+    DownloadDir {
+        name: String,
+        target: Address,
+        #[serde(default)]
+        resume: bool,
+        #[serde(default)]
+        dest: Option<String>,
+        #[serde(default)]
+        on_collision: CollisionPolicy,
+    },
+    // download `name` from `target`, and the same content from any of `peers` that have it.
+    SwarmDownload {
+        name: String,
+        target: Address,
+        peers: Vec<Address>,
+        #[serde(default)]
+        resume: bool,
+    },
+    FileHash { path: String },
+    // from our workers: the hash of our file at path, worked out for a FileHash. None if it couldn't be.
+    Hashed { path: String, content: Option<ContentId> },
+    // when we last wrote path, if we know.
+    Modified { path: String },
+    UpdateSettings(TransferSettings),
+    // stop a transfer on both ends. the partial file is kept for resuming unless delete_partial.
+    Cancel {
+        id: String,
+        #[serde(default)]
+        delete_partial: bool,
+    },
+    // hold a transfer without losing progress, and pick it back up later.
+    Pause { id: String },
+    Resume { id: String },
+    // downloads wait in a queue until there's room under these limits.
+    UpdateQueueLimits(QueueLimits),
+    // move a queued download to the front of the queue.
+    Prioritize { id: u64 },
+    Unqueue { id: u64 },
+    // from our workers.
+    TransferEvent(TransferEvent),
+    // finished transfers, newest first.
+    History(HistoryFilter),
+    // everything running now, then everything queued.
+    ListTransfers,
+    // files anywhere in the drive that the requester may see and that match.
+    Search(SearchQuery),
+    // files the requester may see containing every word of query. needs indexing on.
+    SearchContent { query: String },
+    // keep a full-text index of our text files, or drop it.
+    SetIndexing(bool),
+    // a small png of an image the requester may see, in the response blob.
+    Thumbnail { path: String },
+    // offer our file `name` to `target`. it's only sent if they accept.
+    Send { name: String, target: Address },
+    // from a peer: they'd like to send us a file.
+    Offer { id: u64, name: String, size: u64 },
+    // answer an offer in our inbox. accepting queues its download.
+    AcceptOffer {
+        node: String,
+        id: u64,
+        #[serde(default)]
+        dest: Option<String>,
+        #[serde(default)]
+        on_collision: CollisionPolicy,
+    },
+    DeclineOffer { node: String, id: u64 },
+    // from a peer: what they made of our offer.
+    OfferAnswered { id: u64, accepted: bool },
+    // from a peer: they kept their own copy rather than download our file `name`, so an offer of it is used up.
+    DownloadSkipped { name: String },
     Delete { name: String },
     CreateDir { name: String },
     Move { source_path: String, target_path: String },
@@ -38,54 +157,667 @@ pub enum KinoRequest {
 #[derive(Serialize, Deserialize, Debug)]
 pub enum KinoResponse {
     ListFiles(Vec<KinoFileInfo>),
+    // next is the cursor for the following page, if there is one.
+    ListPage { files: Vec<KinoFileInfo>, next: Option<String> },
     Download { name: String, worker: Address },
+    FileHash(Option<ContentId>),
+    Modified(Option<u64>),
+    History(Vec<HistoryEntry>),
+    ListTransfers(Vec<TransferInfo>),
+    // flat, sorted by name. folders come back unlisted.
+    Search(Vec<KinoFileInfo>),
+    SearchContent(Vec<KinoFileInfo>),
+    // whether there's a thumbnail in the blob.
+    Thumbnail(bool),
     Done,
     Started,
 }
 
+/// what to do when a file we're saving would land on one we already have.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum CollisionPolicy {
+    #[default]
+    Overwrite,
+    // save it next to ours as `name (1).ext`.
+    Rename,
+    Skip,
+    // replace ours only if theirs was written more recently.
+    KeepNewer,
+}
+
+/// identifies a file by what's in it rather than where it is.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContentId {
+    pub size: u64,
+    pub hash: String,
+}
+
+/// how our workers send files, editable at runtime.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransferSettings {
+    // bounds for the sender's adaptive chunk size, in bytes.
+    pub min_chunk_size: u64,
+    pub max_chunk_size: u64,
+    // caps on what we send in bytes/s, over all transfers and to any one node. None is unlimited.
+    #[serde(default)]
+    pub max_upload_rate: Option<u64>,
+    #[serde(default)]
+    pub node_upload_rates: HashMap<String, u64>,
+}
+
+impl Default for TransferSettings {
+    fn default() -> Self {
+        TransferSettings {
+            min_chunk_size: 65536,    // 64KB
+            max_chunk_size: 8388608,  // 8MB
+            max_upload_rate: None,
+            node_upload_rates: HashMap::new(),
+        }
+    }
+}
+
+/// how many of our workers may run at once, in total and with any single peer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueueLimits {
+    pub max_active: usize,
+    pub max_per_peer: usize,
+}
+
+impl Default for QueueLimits {
+    fn default() -> Self {
+        QueueLimits {
+            max_active: 4,
+            max_per_peer: 2,
+        }
+    }
+}
+
+/// a download waiting for a free worker slot.
 #[derive(Serialize, Deserialize, Debug)]
-pub struct KinoFileInfo {
+pub struct QueuedTransfer {
+    pub id: u64,
+    // the node we'll be downloading from, for the per-peer limit.
+    pub peer: String,
+    pub request: KinoRequest,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    None,
+    Gzip,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TransferStats {
+    pub codec: Codec,
+    pub bytes: u64,
+    pub wire_bytes: u64,
+}
+
+/// something that happened to a transfer. ids are the receiving worker's address,
+/// except for Queued, which carries the queue id since there's no worker yet.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TransferEvent {
+    pub id: String,
     pub name: String,
+    pub direction: Direction,
+    pub kind: TransferEventKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Send,
+    Receive,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum TransferEventKind {
+    Queued,
+    Started { size: u64, hash: String },
+    Progress {
+        size: u64,
+        progress: u64,
+        rate: u64,
+        eta: Option<u64>,
+        stats: TransferStats,
+    },
+    Paused,
+    Resumed,
+    Verifying,
+    Completed,
+    Failed { reason: String },
+    Cancelled,
+    // not downloaded, we kept the file we had.
+    Skipped,
+}
+
+/// a finished transfer, kept in our history.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub peer: String,
+    pub path: String,
     pub size: u64,
+    pub direction: Direction,
+    // unix seconds.
+    pub started: u64,
+    pub ended: u64,
+    pub outcome: TransferOutcome,
+    // why it failed, if it did.
+    pub reason: Option<String>,
+    pub hash: Option<String>,
+}
+
+/// a file a peer offered to send us, waiting in our inbox for an answer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IncomingOffer {
+    // the offering node's id for it.
+    pub id: u64,
+    // their kino_files, which we download it from.
+    pub from: Address,
+    pub name: String,
+    pub size: u64,
+    pub received: u64,
+}
+
+/// a file we offered to a peer. until they download it or decline,
+/// they may download it whatever its permissions say.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SentOffer {
+    pub id: u64,
+    pub node: String,
+    pub name: String,
+    pub size: u64,
+    pub sent: u64,
+    pub accepted: bool,
+}
+
+impl SentOffer {
+    fn expired(&self) -> bool {
+        now() >= self.sent + OFFER_EXPIRY
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TransferState {
+    Queued,
+    Active,
+    Paused,
+    Verifying,
+}
+
+/// a transfer that's running or waiting to, as ListTransfers reports it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransferInfo {
+    // the receiving worker's address, or for a queued download its queue id.
+    pub id: String,
+    pub name: String,
+    pub peer: String,
+    pub direction: Direction,
+    pub bytes: u64,
+    // 0 until the sender has told us.
+    pub size: u64,
+    pub rate: u64,
+    pub state: TransferState,
+}
+
+/// a directory being downloaded file by file, tracked so we can report on it as a whole.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FolderDownload {
+    // the folder's path in our files drive.
+    pub name: String,
+    pub peer: String,
+    // by path in our files drive.
+    pub files: HashMap<String, FolderFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FolderFile {
+    pub size: u64,
+    // only brought up to date once the file is done, until then the count lives with its transfer.
+    pub bytes: u64,
+    pub outcome: Option<TransferOutcome>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TransferOutcome {
+    Completed,
+    Failed,
+    Cancelled,
+    Skipped,
+}
+
+/// which history entries to return. every field that's set has to match.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct HistoryFilter {
+    #[serde(default)]
+    pub peer: Option<String>,
+    // matches any path containing it.
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub direction: Option<Direction>,
+    #[serde(default)]
+    pub outcome: Option<TransferOutcome>,
+    // unix seconds, entries that ended in [since, until].
+    #[serde(default)]
+    pub since: Option<u64>,
+    #[serde(default)]
+    pub until: Option<u64>,
+}
+
+impl HistoryFilter {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.peer.as_ref().is_none_or(|peer| *peer == entry.peer)
+            && self.path.as_ref().is_none_or(|path| entry.path.contains(path.as_str()))
+            && self.direction.is_none_or(|direction| direction == entry.direction)
+            && self.outcome.is_none_or(|outcome| outcome == entry.outcome)
+            && self.since.is_none_or(|since| entry.ended >= since)
+            && self.until.is_none_or(|until| entry.ended <= until)
+    }
+
+    /// files/history?peer=..&path=..&direction=Send&outcome=Failed&since=..&until=..
+    fn from_query(params: &HashMap<String, String>) -> Self {
+        let parse = |key: &str| params.get(key).and_then(|value| {
+            serde_json::from_value(serde_json::Value::String(value.clone())).ok()
+        });
+        HistoryFilter {
+            peer: params.get("peer").cloned(),
+            path: params.get("path").cloned(),
+            direction: parse("direction"),
+            outcome: parse("outcome"),
+            since: params.get("since").and_then(|since| since.parse().ok()),
+            until: params.get("until").and_then(|until| until.parse().ok()),
+        }
+    }
+}
+
+/// which files to find. every field that's set has to match.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SearchQuery {
+    // a glob with * and ?, or else any part of the file name. case doesn't matter.
+    #[serde(default)]
+    pub name: Option<String>,
+    // bytes, inclusive.
+    #[serde(default)]
+    pub min_size: Option<u64>,
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    // unix seconds, files last modified in [since, until]. files we don't know the time of never match.
+    #[serde(default)]
+    pub since: Option<u64>,
+    #[serde(default)]
+    pub until: Option<u64>,
+    // start of a MIME type, `image` or `text/markdown`, or `directory` for folders.
+    #[serde(default)]
+    pub kind: Option<String>,
+}
+
+impl SearchQuery {
+    fn matches(&self, file: &KinoFileInfo) -> bool {
+        let file_name = file.name.rsplit('/').next().unwrap_or(&file.name);
+        self.name.as_ref().is_none_or(|name| name_matches(name, file_name))
+            && self.min_size.is_none_or(|min_size| file.size.is_some_and(|size| size >= min_size))
+            && self.max_size.is_none_or(|max_size| file.size.is_some_and(|size| size <= max_size))
+            && self.since.is_none_or(|since| file.modified.is_some_and(|modified| modified >= since))
+            && self.until.is_none_or(|until| file.modified.is_some_and(|modified| modified <= until))
+            && self.kind.as_ref().is_none_or(|kind| match (&file.dir, &file.mime) {
+                (Some(_), _) => kind == "directory",
+                (None, Some(mime)) => mime.starts_with(kind.as_str()),
+                (None, None) => false,
+            })
+    }
+
+    /// files/search?node=..&name=..&min_size=..&max_size=..&since=..&until=..&kind=..
+    fn from_query(params: &HashMap<String, String>) -> Self {
+        let number = |key: &str| params.get(key).and_then(|value| value.parse().ok());
+        SearchQuery {
+            name: params.get("name").cloned(),
+            min_size: number("min_size"),
+            max_size: number("max_size"),
+            since: number("since"),
+            until: number("until"),
+            kind: params.get("kind").cloned(),
+        }
+    }
+}
+
+fn name_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let name = name.to_lowercase();
+    if !pattern.contains(['*', '?']) {
+        return name.contains(&pattern);
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // walk both, remembering the last * so a mismatch can let it swallow one more character.
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct KinoFileInfo {
+    pub name: String,
+    // a directory's is the total of everything under it, None if some of that wasn't listed.
+    #[serde(default)]
+    pub size: Option<u64>,
     pub dir: Option<Vec<KinoFileInfo>>,
+    // a directory past the depth asked for, its contents weren't listed.
+    #[serde(default)]
+    pub unlisted: bool,
+    // when we last wrote it, if we know.
+    #[serde(default)]
+    pub modified: Option<u64>,
+    #[serde(default)]
+    pub mime: Option<String>,
+    // its content hash, if it's been worked out.
+    #[serde(default)]
+    pub hash: Option<String>,
+    // how many entries a directory has.
+    #[serde(default)]
+    pub children: Option<u64>,
+}
+
+/// which part of a files drive to list. every field is optional, the default is all of it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ListOptions {
+    // a directory in the drive, its full path as listed.
+    #[serde(default)]
+    pub path: Option<String>,
+    // how many levels down to list, 1 is just what's in path.
+    #[serde(default)]
+    pub depth: Option<u32>,
+    // page size, capped at MAX_LIST_PAGE. what's listed under folders counts towards it.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    // list what comes after this, the `next` of the page before.
+    #[serde(default)]
+    pub cursor: Option<String>,
+}
+
+impl ListOptions {
+    /// files?path=..&depth=1&limit=100&cursor=..
+    fn from_query(params: &HashMap<String, String>) -> Self {
+        ListOptions {
+            path: params.get("path").cloned(),
+            depth: params.get("depth").and_then(|depth| depth.parse().ok()),
+            limit: params.get("limit").and_then(|limit| limit.parse().ok()),
+            cursor: params.get("cursor").cloned(),
+        }
+    }
 }
 
+/// a transfer with one of our workers on it. its id is the receiving worker's address.
+struct ActiveTransfer {
+    worker: Address,
+    name: String,
+    peer: String,
+    direction: Direction,
+    state: TransferState,
+    // bytes moved so far, and the measured rate in bytes/s.
+    bytes: u64,
+    rate: u64,
+    // for the history: when we started it, and what the worker told us it's moving.
+    started: u64,
+    size: u64,
+    hash: Option<String>,
+}
+
+type Transfers = HashMap<String, ActiveTransfer>;
+// our files being hashed for FileHash requests, by path, and the worker hashing each.
+type Hashing = HashMap<String, Address>;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum WorkerRequest {
     Initialize {
         name: String,
         target_worker: Option<Address>,
+        resume: bool,
+        hash: Option<String>,
+        settings: TransferSettings,
     },
+    Cancel { delete_partial: bool },
+    Pause,
+    Resume,
+    SetRateLimit(Option<u64>),
+    // hash our file at path and answer the request that was passed on with it.
+    Hash { path: String },
+    // let a hashing worker go once nothing else is waiting on its hash.
+    Exit,
+    // make a thumbnail of the image at path, cache it and answer the request that was passed on with it.
+    // http says whether that's a request from our http server rather than from a peer.
+    Thumbnail { path: String, cached: String, http: bool },
 }
 
+/// a swarm download waiting on the hash of the file it's after, carried in the FileHash request's context.
+#[derive(Serialize, Deserialize, Debug)]
+struct SwarmContext {
+    path: String,
+    local_name: String,
+    target: Address,
+    peers: Vec<Address>,
+    resume: bool,
+    worker: Address,
+}
+
+/// everything source may see in our files drive.
 fn ls_files(source: &Address, our: &Address, files_dir: &Directory) -> anyhow::Result<Vec<KinoFileInfo>> {
-    let entries = files_dir.read()?;
-    let files: Vec<KinoFileInfo> = entries
+    let state = load_state();
+    ls_dir(source, our, &state, files_dir, None)
+}
+
+/// what source may see in dir, depth levels down, or all the way with None.
+fn ls_dir(
+    source: &Address,
+    our: &Address,
+    state: &FileTransferState,
+    dir: &Directory,
+    depth: Option<u32>,
+) -> anyhow::Result<Vec<KinoFileInfo>> {
+    Ok(visible_entries(source, our, state, dir)?
         .iter()
-        .filter_map(|file| {
-            if source.node != our.node && !node_has_perms_to_path(&source.node, &file.path) {
-                return None;
-            }
-            match file.file_type {
-                FileType::File => match metadata(&file.path, None) {
-                    Ok(metadata) => Some(KinoFileInfo {
-                        name: file.path.clone(),
-                        size: metadata.len,
-                        dir: None,
-                    }),
-                    Err(_) => None,
-                },
-                FileType::Directory => Some(KinoFileInfo {
-                    name: file.path.clone(),
-                    size: 0,
-                    dir: Some(ls_files(source, our, &open_dir(&file.path, false, None).unwrap()).unwrap()),
-                }),
-                _ => None,
+        .filter_map(|entry| file_info(source, our, state, entry, depth))
+        .collect())
+}
+
+fn visible_entries(
+    source: &Address,
+    our: &Address,
+    state: &FileTransferState,
+    dir: &Directory,
+) -> anyhow::Result<Vec<DirEntry>> {
+    Ok(dir
+        .read()?
+        .into_iter()
+        .filter(|entry| !is_internal_path(&entry.path))
+        .filter(|entry| source.node == our.node || permitted(state, &source.node, &entry.path))
+        .collect())
+}
+
+fn file_info(
+    source: &Address,
+    our: &Address,
+    state: &FileTransferState,
+    entry: &DirEntry,
+    depth: Option<u32>,
+) -> Option<KinoFileInfo> {
+    let modified = state.modified.get(entry.path.trim_start_matches('/')).copied();
+    match entry.file_type {
+        FileType::File => {
+            let size = metadata(&entry.path, None).ok()?.len;
+            Some(KinoFileInfo {
+                name: entry.path.clone(),
+                size: Some(size),
+                modified,
+                mime: mime_guess::from_path(&entry.path).first_raw().map(str::to_string),
+                // only a hash we've already worked out, and that still fits the file.
+                hash: state
+                    .content_ids
+                    .get(entry.path.trim_start_matches('/'))
+                    .filter(|content| content.size == size)
+                    .map(|content| content.hash.clone()),
+                ..Default::default()
+            })
+        }
+        FileType::Directory => {
+            // past the last level we only say it's there, and how many things are in it. its size
+            // would take walking all of it, so that's left unknown. a folder we can't read lists as empty.
+            let dir = open_dir(&entry.path, false, None).ok();
+            let unlisted = depth.is_some_and(|depth| depth <= 1);
+            let (children, count, size) = match (unlisted, dir) {
+                (_, None) => (vec![], 0, Some(0)),
+                (true, Some(dir)) => {
+                    let entries = visible_entries(source, our, state, &dir).unwrap_or_default();
+                    (vec![], entries.len() as u64, None)
+                }
+                (false, Some(dir)) => {
+                    let children = ls_dir(source, our, state, &dir, depth.map(|depth| depth - 1)).unwrap_or_default();
+                    let count = children.len() as u64;
+                    // unknown as soon as any of it is.
+                    let size = children.iter().map(|child| child.size).sum();
+                    (children, count, size)
+                }
+            };
+            Some(KinoFileInfo {
+                name: entry.path.clone(),
+                size,
+                dir: Some(children),
+                unlisted,
+                modified,
+                children: Some(count),
+                ..Default::default()
+            })
+        }
+        _ => None,
+    }
+}
+
+/// up to MAX_LIST_PAGE files and folders source may see that match query.
+fn search_files(
+    source: &Address,
+    our: &Address,
+    files_dir: &Directory,
+    query: &SearchQuery,
+) -> anyhow::Result<Vec<KinoFileInfo>> {
+    let mut found = Vec::new();
+    let mut stack = ls_files(source, our, files_dir)?;
+    while let Some(mut file) = stack.pop() {
+        if let Some(children) = file.dir.take() {
+            stack.extend(children);
+            file.dir = Some(vec![]);
+            file.unlisted = true;
+        }
+        if query.matches(&file) {
+            found.push(file);
+        }
+    }
+    found.sort_by(|a, b| a.name.cmp(&b.name));
+    found.truncate(MAX_LIST_PAGE);
+    Ok(found)
+}
+
+
+/// one page of what source may see under options.path, sorted by name.
+fn list_files(
+    source: &Address,
+    our: &Address,
+    files_dir: &Directory,
+    options: &ListOptions,
+) -> anyhow::Result<(Vec<KinoFileInfo>, Option<String>)> {
+    let dir = match options.path {
+        Some(ref path) => {
+            let inside = path.trim_start_matches('/').starts_with(files_dir.path.trim_start_matches('/'));
+            if !inside || is_internal_path(path) || !node_can_see_dir(source, our, files_dir, path) {
+                return Ok((vec![], None));
             }
-        })
-        .collect();
+            match open_dir(path, false, None) {
+                Ok(dir) => dir,
+                Err(_) => return Ok((vec![], None)),
+            }
+        }
+        None => open_dir(&files_dir.path, false, None)?,
+    };
 
-    Ok(files)
+    let state = load_state();
+    let mut entries = visible_entries(source, our, &state, &dir)?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    if let Some(ref cursor) = options.cursor {
+        entries.retain(|entry| entry.path > *cursor);
+    }
+    let limit = options.limit.unwrap_or(MAX_LIST_PAGE).clamp(1, MAX_LIST_PAGE);
+    let mut files: Vec<KinoFileInfo> = Vec::new();
+    let mut listed = 0;
+    let mut next = None;
+    for entry in &entries {
+        if listed == limit {
+            next = files.last().map(|file| file.name.clone());
+            break;
+        }
+        let Some(mut file) = file_info(source, our, &state, entry, options.depth) else {
+            continue;
+        };
+        if listed + listed_count(&file) > limit {
+            // too much under it for what's left of the page. it comes back unlisted, to be paged through on its own.
+            let Some(unlisted) = file_info(source, our, &state, entry, Some(1)) else {
+                continue;
+            };
+            file = unlisted;
+        }
+        listed += listed_count(&file);
+        files.push(file);
+    }
+    Ok((files, next))
+}
+
+/// how many entries file takes up in a listing, itself and everything listed under it.
+fn listed_count(file: &KinoFileInfo) -> usize {
+    1 + file.dir.iter().flatten().map(listed_count).sum::<usize>()
+}
+
+/// whether source may see path, a directory in our files drive: every folder on the way down to it
+/// has to be visible to them, as it is when ls_files walks there.
+fn node_can_see_dir(source: &Address, our: &Address, files_dir: &Directory, path: &str) -> bool {
+    if source.node == our.node {
+        return true;
+    }
+    let root = files_dir.path.trim_start_matches('/');
+    let relative = path.trim_start_matches('/').strip_prefix(root).unwrap_or("").trim_matches('/');
+    let mut prefix = String::new();
+    for segment in relative.split('/').filter(|segment| !segment.is_empty()) {
+        if !prefix.is_empty() {
+            prefix.push('/');
+        }
+        prefix.push_str(segment);
+        if !node_has_perms_to_path(&source.node, &prefix) {
+            return false;
+        }
+    }
+    true
+}
+
+fn is_internal_path(path: &str) -> bool {
+    path.split('/').any(|segment| segment == TRANSFERS_DIR || segment == THUMBNAILS_DIR)
 }
 
 fn flatten_files_list(files: Vec<KinoFileInfo>) -> anyhow::Result<Vec<KinoFileInfo>> {
@@ -99,11 +831,7 @@ fn flatten_files_list(files: Vec<KinoFileInfo>) -> anyhow::Result<Vec<KinoFileIn
                 stack.push(file);
             }
         } else {
-            flat_list.push(KinoFileInfo {
-                name: file_info.name,
-                size: file_info.size,
-                dir: None,
-            });
+            flat_list.push(file_info);
         }
     }
 
@@ -111,8 +839,13 @@ fn flatten_files_list(files: Vec<KinoFileInfo>) -> anyhow::Result<Vec<KinoFileIn
 }
 
 fn node_has_perms_to_path(node: &String, path: &String) -> bool {
+    let state = load_state();
+    permitted(&state, node, path)
+}
+
+/// node_has_perms_to_path against state we've already read, for checking many paths at once.
+fn permitted(state: &FileTransferState, node: &str, path: &str) -> bool {
     let path = path.split("/files/").last().unwrap_or(path);
-    let state = get_typed_state(|bytes| Ok(serde_json::from_slice::<FileTransferState>(&bytes)?)).unwrap_or(empty_state());
     // println!("checking perms for path {} from node {} among {:?}", path, node, state.permissions);
     let permissions = state.permissions.get(path);
     match permissions {
@@ -130,24 +863,820 @@ fn node_has_perms_to_path(node: &String, path: &String) -> bool {
     }
 }
 
+/// whether source may download path, a file in our files drive: it has to be there, and visible
+/// to them along with every folder on the way down to it. only that one path is checked.
 fn node_can_download_path(source: &Address, our: &Address, files_dir: &Directory, path: &String) -> anyhow::Result<bool> {
-    // check if source has permission to see the file. if so, they may also download it
-    let files_available_to_node = flatten_files_list(ls_files(source, our, files_dir)?)?;
-    // println!("checking perms for file: {}", name);
-
-    if !files_available_to_node.iter().any(|file| file.name == *path) {
+    let root = files_dir.path.trim_start_matches('/');
+    let inside = path
+        .trim_start_matches('/')
+        .strip_prefix(root)
+        .is_some_and(|rest| rest.starts_with('/') && !rest.split('/').any(|segment| segment == ".."));
+    let is_file = metadata(path, None).is_ok_and(|meta| matches!(meta.file_type, FileType::File));
+    if !inside || is_internal_path(path) || !is_file || !node_can_see_dir(source, our, files_dir, path) {
         println!("kino_files: file {} is not accessible to node {}", path, source.node);
         return Ok(false);
     }
     Ok(true)
 }
 
+/// the hash of our file at path, if we've worked it out since it last changed.
+/// hashing happens in workers, a big file would hold up everything else here.
+fn cached_content_id(path: &str) -> anyhow::Result<Option<ContentId>> {
+    let size = metadata(path, None)?.len;
+    let state = load_state();
+    Ok(state
+        .content_ids
+        .get(path.trim_start_matches('/'))
+        .filter(|content| content.size == size)
+        .cloned())
+}
+
+fn cache_content_id(path: &str, content: ContentId) -> anyhow::Result<()> {
+    let mut state = load_state();
+    state.content_ids.insert(path.trim_start_matches('/').to_string(), content);
+    save_state(&state)?;
+    Ok(())
+}
+
+/// a file of ours was written, moved or removed: drop its cached hash, note when it changed,
+/// and bring the content index up to date with it.
+fn file_changed(files_dir: &Directory, path: &str) -> anyhow::Result<()> {
+    files_changed(files_dir, &[path.to_string()])
+}
+
+/// file_changed for many files at once, reading and writing state and the index just the once.
+fn files_changed(files_dir: &Directory, paths: &[String]) -> anyhow::Result<()> {
+    let mut state = load_state();
+    for path in paths {
+        // listings name files without the leading slash that files_dir.path has.
+        let key = path.trim_start_matches('/');
+        state.content_ids.remove(key);
+        if metadata(path, None).is_ok() {
+            state.modified.insert(key.to_string(), now());
+        } else {
+            state.modified.remove(key);
+        }
+        let _ = remove_file(&thumbnail_path(files_dir, path), None);
+    }
+    save_state(&state)?;
+    if state.indexing {
+        let mut index = ContentIndex::load(&index_path(files_dir));
+        for path in paths {
+            reindex(&mut index, path);
+        }
+        index.save(&index_path(files_dir))?;
+    }
+    Ok(())
+}
+
+/// every file anywhere under the folder at path.
+fn files_under(path: &str) -> Vec<String> {
+    let Ok(entries) = open_dir(path, false, None).and_then(|dir| dir.read()) else {
+        return vec![];
+    };
+    entries
+        .into_iter()
+        .flat_map(|entry| match entry.file_type {
+            FileType::Directory => files_under(&entry.path),
+            FileType::File => vec![entry.path],
+            _ => vec![],
+        })
+        .collect()
+}
+
+fn thumbnail_path(files_dir: &Directory, path: &str) -> String {
+    let root = files_dir.path.trim_start_matches('/');
+    let relative = path.trim_start_matches('/').strip_prefix(root).unwrap_or(path).trim_matches('/');
+    // keyed by a digest of the path, so no two images share a thumbnail.
+    format!("{}/{}/{:x}.png", files_dir.path, THUMBNAILS_DIR, Sha256::digest(relative.as_bytes()))
+}
+
+fn has_thumbnail(path: &str) -> bool {
+    let extension = path.rsplit_once('.').map_or("", |(_, extension)| extension).to_lowercase();
+    THUMBNAIL_EXTENSIONS.contains(&extension.as_str())
+}
+
+/// the small png of the image at path, if one's been made since the image last changed.
+fn cached_thumbnail(files_dir: &Directory, path: &str) -> Option<Vec<u8>> {
+    open_file(&thumbnail_path(files_dir, path), false, None)
+        .ok()
+        .and_then(|file| file.read().ok())
+}
+
+/// have a worker make a thumbnail of the image at path and answer the request in our place.
+/// decoding an image takes a while, and everything else here would wait on it.
+fn make_thumbnail(our: &Address, files_dir: &Directory, path: &str, http: bool) -> anyhow::Result<()> {
+    if !has_thumbnail(path) {
+        return Err(anyhow::anyhow!("{} is not an image we can read", path));
+    }
+    if metadata(path, None)?.len > MAX_THUMBNAIL_SOURCE_SIZE {
+        return Err(anyhow::anyhow!("{} is too big to make a thumbnail of", path));
+    }
+    let worker = spawn_worker(our)?;
+    Request::new()
+        .body(serde_json::to_vec(&WorkerRequest::Thumbnail {
+            path: path.to_string(),
+            cached: thumbnail_path(files_dir, path),
+            http,
+        })?)
+        .target(&worker)
+        .inherit(true)
+        .send()?;
+    Ok(())
+}
+
+fn index_path(files_dir: &Directory) -> String {
+    let package = files_dir.path.rsplit_once('/').map_or("", |(package, _)| package);
+    format!("{}/{}/{}", package, INDEX_DRIVE, INDEX_FILE)
+}
+
+/// index path as it is now: its words if it's a text file we can read, nothing if it's gone.
+fn reindex(index: &mut ContentIndex, path: &str) {
+    let key = path.trim_start_matches('/');
+    let text = match metadata(path, None) {
+        Ok(meta) if meta.file_type == FileType::File && meta.len <= index::MAX_INDEXED_SIZE && index::is_text(path) => {
+            open_file(path, false, None)
+                .ok()
+                .and_then(|file| file.read().ok())
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+        }
+        _ => None,
+    };
+    match text {
+        Some(text) => index.add(key, &text),
+        None => index.remove(key),
+    }
+}
+
+fn rebuild_index(our: &Address, files_dir: &Directory) -> anyhow::Result<()> {
+    let mut index = ContentIndex::default();
+    let files = flatten_files_list(ls_files(our, our, files_dir)?)?;
+    for file in &files {
+        reindex(&mut index, &file.name);
+    }
+    println!("kino_files: indexed {} files", files.len());
+    index.save(&index_path(files_dir))
+}
+
+/// files source may see whose contents have every word of query.
+fn search_content(
+    source: &Address,
+    our: &Address,
+    files_dir: &Directory,
+    query: &str,
+) -> anyhow::Result<Vec<KinoFileInfo>> {
+    let state = load_state();
+    if !state.indexing {
+        return Ok(vec![]);
+    }
+    let hits = ContentIndex::load(&index_path(files_dir)).query(query);
+    let mut found: Vec<KinoFileInfo> = flatten_files_list(ls_files(source, our, files_dir)?)?
+        .into_iter()
+        .filter(|file| hits.binary_search(&file.name.trim_start_matches('/').to_string()).is_ok())
+        .collect();
+    found.sort_by(|a, b| a.name.cmp(&b.name));
+    found.truncate(MAX_LIST_PAGE);
+    Ok(found)
+}
+
+fn modified(path: &str) -> Option<u64> {
+    let state = load_state();
+    state.modified.get(path.trim_start_matches('/')).copied()
+}
+
+fn remote_modified(target: &Address, path: &str) -> anyhow::Result<Option<u64>> {
+    let resp = Request::new()
+        .body(serde_json::to_vec(&KinoRequest::Modified { path: path.to_string() })?)
+        .target(target)
+        .send_and_await_response(5)??;
+    match serde_json::from_slice::<KinoResponse>(resp.body())? {
+        KinoResponse::Modified(modified) => Ok(modified),
+        _ => Err(anyhow::anyhow!("unexpected response to modified request")),
+    }
+}
+
+fn exists(files_dir: &Directory, local_name: &str) -> bool {
+    metadata(&format!("{}/{}", files_dir.path, local_name), None).is_ok()
+}
+
+/// `name (1).ext`, `name (2).ext`, ... whichever is free first.
+fn free_name(files_dir: &Directory, local_name: &str) -> String {
+    let (dir, file) = match local_name.rsplit_once('/') {
+        Some((dir, file)) => (format!("{}/", dir), file),
+        None => (String::new(), local_name),
+    };
+    let (stem, extension) = match file.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (file, String::new()),
+    };
+    let mut n = 1;
+    loop {
+        let candidate = format!("{}{} ({}){}", dir, stem, n, extension);
+        if !exists(files_dir, &candidate) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+/// where a file headed for local_name in our files drive should go under policy,
+/// or None if we keep what we have. incoming_modified is only asked when it matters.
+fn resolve_collision(
+    files_dir: &Directory,
+    local_name: String,
+    policy: CollisionPolicy,
+    incoming_modified: impl FnOnce() -> anyhow::Result<Option<u64>>,
+) -> anyhow::Result<Option<String>> {
+    if !exists(files_dir, &local_name) {
+        return Ok(Some(local_name));
+    }
+    match policy {
+        CollisionPolicy::Overwrite => Ok(Some(local_name)),
+        CollisionPolicy::Rename => Ok(Some(free_name(files_dir, &local_name))),
+        CollisionPolicy::Skip => Ok(None),
+        CollisionPolicy::KeepNewer => {
+            // a file we have no record of writing counts as older than anything.
+            let ours = modified(&format!("{}/{}", files_dir.path, local_name)).unwrap_or(0);
+            Ok(match incoming_modified()? {
+                Some(theirs) if theirs > ours => Some(local_name),
+                _ => None,
+            })
+        }
+    }
+}
+
+/// local_name under dest, both relative to our files drive.
+fn under_dest(dest: &Option<String>, local_name: &str) -> String {
+    match dest.as_deref().map(|dest| dest.trim_matches('/')) {
+        Some(dest) if !dest.is_empty() => format!("{}/{}", dest, local_name),
+        _ => local_name.to_string(),
+    }
+}
+
+/// where an entry of a peer's listing of `path` goes in our copy under `root`.
+/// anything not inside `path`, or that would step out of it or into our own bookkeeping, has no place.
+fn local_path_in(path: &str, root: &str, name: &str) -> Option<String> {
+    if name == path {
+        return Some(root.to_string());
+    }
+    let rest = name.strip_prefix(path)?.strip_prefix('/')?;
+    let safe = rest.split('/').all(|segment| !matches!(segment, "" | "." | ".."));
+    (safe && !is_internal_path(rest)).then(|| format!("{}/{}", root, rest))
+}
+
+fn ensure_parent_dir(files_dir: &Directory, local_name: &str) -> anyhow::Result<()> {
+    if let Some((parent, _)) = local_name.rsplit_once('/') {
+        open_dir(&format!("{}/{}", files_dir.path, parent), true, None)?;
+    }
+    Ok(())
+}
+
+/// find a file visible to source with the given content. a file whose hash we know has to match it;
+/// the path they asked for may also be one we haven't hashed, its worker hashes it and the receiver checks.
+fn find_content(
+    source: &Address,
+    our: &Address,
+    files_dir: &Directory,
+    path: &String,
+    content: &ContentId,
+) -> anyhow::Result<Option<String>> {
+    let mut candidates: Vec<String> = flatten_files_list(ls_files(source, our, files_dir)?)?
+        .into_iter()
+        .filter(|file| file.size == Some(content.size))
+        .map(|file| file.name)
+        .collect();
+    candidates.sort_by_key(|name| name != path);
+
+    for candidate in candidates {
+        match cached_content_id(&candidate)? {
+            Some(known) if known.hash == content.hash => return Ok(Some(candidate)),
+            None if candidate == *path => return Ok(Some(candidate)),
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+fn send_to_transfer_worker(
+    transfers: &Transfers,
+    id: &String,
+    request: &WorkerRequest,
+) -> anyhow::Result<()> {
+    let Some(transfer) = transfers.get(id) else {
+        return Err(anyhow::anyhow!("no active transfer {}", id));
+    };
+    println!("kino_files: {:?} transfer of {}", request, transfer.name);
+    Request::new()
+        .body(serde_json::to_vec(request)?)
+        .target(&transfer.worker)
+        .send()?;
+    Ok(())
+}
+
+/// a file to download: path on target, saved as local_name under our files drive.
+struct FileDownload {
+    path: String,
+    target: Address,
+    resume: bool,
+    content: Option<ContentId>,
+    local_name: String,
+}
+
+/// a folder to download: path on target, saved under dest or the top of our files drive.
+struct DirDownload {
+    path: String,
+    target: Address,
+    resume: bool,
+    dest: Option<String>,
+    on_collision: CollisionPolicy,
+}
+
+/// start receiving a file from its target, and ask them to send it.
+fn start_download(
+    our: &Address,
+    files_dir: &Directory,
+    transfers: &mut Transfers,
+    download: FileDownload,
+) -> anyhow::Result<()> {
+    let FileDownload { path, target, resume, content, local_name } = download;
+    ensure_parent_dir(files_dir, &local_name)?;
+    let our_worker_address = spawn_worker(our)?;
+    let _resp = Request::new()
+        .body(serde_json::to_vec(&WorkerRequest::Initialize {
+            name: local_name.to_string(),
+            target_worker: None,
+            resume,
+            hash: content.as_ref().map(|content| content.hash.clone()),
+            settings: TransferSettings::default(),
+        })?)
+        .target(&our_worker_address)
+        .send_and_await_response(5)??;
+    transfers.insert(
+        our_worker_address.to_string(),
+        ActiveTransfer {
+            worker: our_worker_address.clone(),
+            name: local_name.to_string(),
+            peer: target.node.clone(),
+            direction: Direction::Receive,
+            state: TransferState::Active,
+            bytes: 0,
+            rate: 0,
+            started: now(),
+            size: 0,
+            hash: None,
+        },
+    );
+
+    // send our initialized worker address to the other node
+    Request::new()
+        .body(serde_json::to_vec(&KinoRequest::Download {
+            name: path.to_string(),
+            target: our_worker_address,
+            resume,
+            content,
+            save_as: None,
+            dest: None,
+            on_collision: CollisionPolicy::default(),
+        })?)
+        .target(&target)
+        .send()?;
+    Ok(())
+}
+
+/// download path from target, and the same content from any of peers that have it.
+/// the worker is spawned now so it holds its place in the queue, and set going once
+/// target tells us what the file is: hashing it can take them a while.
+fn start_swarm_download(
+    our: &Address,
+    transfers: &mut Transfers,
+    path: String,
+    target: Address,
+    peers: Vec<Address>,
+    resume: bool,
+) -> anyhow::Result<()> {
+    let our_worker_address = spawn_worker(our)?;
+    let local_name = path.split("/").last().unwrap_or(&path).to_string();
+    transfers.insert(
+        our_worker_address.to_string(),
+        ActiveTransfer {
+            worker: our_worker_address.clone(),
+            name: local_name.clone(),
+            peer: target.node.clone(),
+            direction: Direction::Receive,
+            state: TransferState::Active,
+            bytes: 0,
+            rate: 0,
+            started: now(),
+            size: 0,
+            hash: None,
+        },
+    );
+
+    Request::new()
+        .body(serde_json::to_vec(&KinoRequest::FileHash { path: path.clone() })?)
+        .target(&target)
+        .context(serde_json::to_vec(&SwarmContext {
+            path,
+            local_name,
+            target: target.clone(),
+            peers,
+            resume,
+            worker: our_worker_address,
+        })?)
+        .expects_response(SWARM_HASH_TIMEOUT)
+        .send()?;
+    Ok(())
+}
+
+/// target has told us what the swarm download is: ask everyone for that content.
+fn continue_swarm_download(channel_id: &mut u32, swarm: SwarmContext, body: &[u8]) -> anyhow::Result<()> {
+    let Ok(KinoResponse::FileHash(Some(content))) = serde_json::from_slice::<KinoResponse>(body) else {
+        let reason = format!("{} has no file {}", swarm.target.node, swarm.path);
+        return abandon_swarm_download(channel_id, swarm, reason);
+    };
+
+    let _resp = Request::new()
+        .body(serde_json::to_vec(&WorkerRequest::Initialize {
+            name: swarm.local_name.clone(),
+            target_worker: None,
+            resume: swarm.resume,
+            hash: Some(content.hash.clone()),
+            settings: TransferSettings::default(),
+        })?)
+        .target(&swarm.worker)
+        .send_and_await_response(5)??;
+
+    println!("kino_files: swarm downloading {} from {} peers", swarm.path, swarm.peers.len() + 1);
+    for peer in std::iter::once(&swarm.target).chain(swarm.peers.iter()) {
+        Request::new()
+            .body(serde_json::to_vec(&KinoRequest::Download {
+                name: swarm.path.clone(),
+                target: swarm.worker.clone(),
+                resume: swarm.resume,
+                content: Some(content.clone()),
+                save_as: None,
+                dest: None,
+                on_collision: CollisionPolicy::default(),
+            })?)
+            .target(peer)
+            .send()?;
+    }
+    Ok(())
+}
+
+/// we never found out what the swarm download is: give up on it. its worker reports it cancelled.
+fn abandon_swarm_download(channel_id: &mut u32, swarm: SwarmContext, reason: String) -> anyhow::Result<()> {
+    println!("kino_files: swarm download of {} failed: {}", swarm.path, reason);
+    push_error_via_ws(channel_id, format!("swarm download of {} failed: {}", swarm.path, reason));
+    Request::new()
+        .body(serde_json::to_vec(&WorkerRequest::Cancel { delete_partial: false })?)
+        .target(&swarm.worker)
+        .send()?;
+    Ok(())
+}
+
+/// split the upload caps between our sending workers: the global one evenly over all of them,
+/// a node's evenly over those sending to it. each worker gets the tighter of its two shares.
+fn apply_rate_limits(transfers: &Transfers) -> anyhow::Result<()> {
+    let state = load_state();
+    let sending: Vec<&ActiveTransfer> = transfers.values().filter(|transfer| transfer.direction == Direction::Send).collect();
+    let global_share = state
+        .settings
+        .max_upload_rate
+        .map(|rate| (rate / sending.len().max(1) as u64).max(1));
+
+    for transfer in &sending {
+        let to_node = sending.iter().filter(|other| other.peer == transfer.peer).count() as u64;
+        let node_share = state
+            .settings
+            .node_upload_rates
+            .get(&transfer.peer)
+            .map(|rate| (rate / to_node).max(1));
+        let limit = match (global_share, node_share) {
+            (Some(global), Some(node)) => Some(global.min(node)),
+            (global, node) => global.or(node),
+        };
+        Request::new()
+            .body(serde_json::to_vec(&WorkerRequest::SetRateLimit(limit))?)
+            .target(&transfer.worker)
+            .send()?;
+    }
+    Ok(())
+}
+
+/// every page of target's listing for options.
+fn fetch_file_list(target: &Address, mut options: ListOptions) -> anyhow::Result<Vec<KinoFileInfo>> {
+    let mut all = Vec::new();
+    loop {
+        let resp = Request::new()
+            .body(serde_json::to_vec(&KinoRequest::ListPage(options.clone()))?)
+            .target(target)
+            .send_and_await_response(5)??;
+        let Ok(KinoResponse::ListPage { files, next }) = serde_json::from_slice::<KinoResponse>(resp.body()) else {
+            return Err(anyhow::anyhow!("no file list from {}", target.node));
+        };
+        all.extend(files);
+        match next {
+            Some(next) => options.cursor = Some(next),
+            None => return Ok(all),
+        }
+    }
+}
+
+/// recreate a directory from its target under our files drive, and queue a download
+/// for every file in it that we're allowed to see.
+fn download_dir(
+    our: &Address,
+    transfers: &mut Transfers,
+    channel_id: &mut u32,
+    files_dir: &Directory,
+    download: DirDownload,
+) -> anyhow::Result<()> {
+    let DirDownload { path, target, resume, dest, mut on_collision } = download;
+    let files = fetch_file_list(
+        &target,
+        ListOptions {
+            path: Some(path.clone()),
+            ..Default::default()
+        },
+    )?;
+    let dir = KinoFileInfo {
+        name: path.clone(),
+        dir: Some(files),
+        ..Default::default()
+    };
+
+    // our copy keeps everything from the folder's own name down. renaming applies
+    // to the folder as a whole, whatever's in a fresh copy can't collide.
+    let folder_name = path.rsplit('/').next().unwrap_or(&path);
+    let mut root = under_dest(&dest, folder_name);
+    if on_collision == CollisionPolicy::Rename && exists(files_dir, &root) {
+        root = free_name(files_dir, &root);
+        on_collision = CollisionPolicy::Overwrite;
+    }
+
+    let mut folder = FolderDownload {
+        name: root.clone(),
+        peer: target.node.clone(),
+        files: HashMap::new(),
+    };
+    let mut downloads = Vec::new();
+    let mut stack = vec![dir];
+    while let Some(entry) = stack.pop() {
+        let Some(local_path) = local_path_in(&path, &root, &entry.name) else {
+            println!("kino_files: skipping {} from {}, it isn't inside {}", entry.name, target.node, path);
+            continue;
+        };
+        match entry.dir {
+            Some(children) => {
+                open_dir(&format!("{}/{}", files_dir.path, local_path), true, None)?;
+                stack.extend(children);
+            }
+            None => {
+                folder.files.insert(
+                    local_path.clone(),
+                    FolderFile {
+                        size: entry.size.unwrap_or(0),
+                        bytes: 0,
+                        outcome: None,
+                    },
+                );
+                downloads.push((entry.name, local_path));
+            }
+        }
+    }
+    println!(
+        "kino_files: downloading {} files in {} from {}",
+        downloads.len(),
+        path,
+        target.node
+    );
+    push_file_update_via_ws(channel_id);
+    if downloads.is_empty() {
+        return Ok(());
+    }
+
+    let mut state = load_state();
+    push_folder_via_ws(channel_id, &folder);
+    state.folders.push(folder);
+    save_state(&state)?;
+
+    for (name, local_path) in downloads {
+        enqueue(
+            our,
+            files_dir,
+            transfers,
+            channel_id,
+            target.node.clone(),
+            KinoRequest::Download {
+                name,
+                target: target.clone(),
+                resume,
+                content: None,
+                save_as: Some(local_path),
+                dest: None,
+                on_collision,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// fold a file's event into the folder download it's part of, if any, and report on the folder.
+/// bytes still on their way are only kept in transfers, state is written once a file is done.
+fn update_folder(channel_id: &mut u32, transfers: &Transfers, event: &TransferEvent) -> anyhow::Result<()> {
+    let outcome = match event.kind {
+        TransferEventKind::Progress { .. } => None,
+        TransferEventKind::Completed => Some(TransferOutcome::Completed),
+        TransferEventKind::Failed { .. } => Some(TransferOutcome::Failed),
+        TransferEventKind::Cancelled => Some(TransferOutcome::Cancelled),
+        TransferEventKind::Skipped => Some(TransferOutcome::Skipped),
+        _ => return Ok(()),
+    };
+    let mut state = load_state();
+    let Some(index) = state
+        .folders
+        .iter()
+        .position(|folder| folder.files.contains_key(&event.name))
+    else {
+        return Ok(());
+    };
+
+    let folder = &mut state.folders[index];
+    if let Some(outcome) = outcome {
+        if let Some(file) = folder.files.get_mut(&event.name) {
+            if matches!(outcome, TransferOutcome::Completed | TransferOutcome::Skipped) {
+                file.bytes = file.size;
+            }
+            file.outcome = Some(outcome);
+        }
+    }
+    let mut shown = folder.clone();
+    for transfer in transfers.values().filter(|transfer| transfer.direction == Direction::Receive) {
+        if let Some(file) = shown.files.get_mut(&transfer.name).filter(|file| file.outcome.is_none()) {
+            file.bytes = transfer.bytes;
+        }
+    }
+    push_folder_via_ws(channel_id, &shown);
+    if outcome.is_none() {
+        return Ok(());
+    }
+    if folder.files.values().all(|file| file.outcome.is_some()) {
+        println!("kino_files: folder {} finished", folder.name);
+        state.folders.remove(index);
+    }
+    save_state(&state)?;
+    Ok(())
+}
+
+fn enqueue(
+    our: &Address,
+    files_dir: &Directory,
+    transfers: &mut Transfers,
+    channel_id: &mut u32,
+    peer: String,
+    request: KinoRequest,
+) -> anyhow::Result<()> {
+    let name = match &request {
+        KinoRequest::Download { save_as: Some(save_as), .. } => save_as.clone(),
+        KinoRequest::Download { name, .. } | KinoRequest::SwarmDownload { name, .. } => {
+            name.split("/").last().unwrap_or(name).to_string()
+        }
+        _ => return Err(anyhow::anyhow!("can't queue request {:?}", request)),
+    };
+    let mut state = load_state();
+    state.next_queue_id += 1;
+    state.queue.push(QueuedTransfer {
+        id: state.next_queue_id,
+        peer,
+        request,
+    });
+    save_state(&state)?;
+    push_transfer_event_via_ws(
+        channel_id,
+        &TransferEvent {
+            id: state.next_queue_id.to_string(),
+            name,
+            direction: Direction::Receive,
+            kind: TransferEventKind::Queued,
+        },
+    );
+    push_queue_via_ws(channel_id, &state, transfers);
+    run_queue(our, files_dir, transfers, channel_id)
+}
+
+/// start queued downloads in order, skipping any whose peer is at its limit, until we're at max_active.
+fn run_queue(
+    our: &Address,
+    files_dir: &Directory,
+    transfers: &mut Transfers,
+    channel_id: &mut u32,
+) -> anyhow::Result<()> {
+    let mut started = false;
+    loop {
+        // starting or skipping a download can touch state, so read it fresh each time.
+        let mut state = load_state();
+        // the limits are on our downloads, uploads peers start on us aren't queued.
+        let downloads: Vec<&ActiveTransfer> = transfers
+            .values()
+            .filter(|transfer| transfer.direction == Direction::Receive)
+            .collect();
+        if downloads.len() >= state.queue_limits.max_active {
+            break;
+        }
+        let Some(i) = state.queue.iter().position(|queued| {
+            downloads.iter().filter(|transfer| transfer.peer == queued.peer).count()
+                < state.queue_limits.max_per_peer
+        }) else {
+            break;
+        };
+        let queued = state.queue.remove(i);
+        // persist first, so a download that fails to start isn't retried forever.
+        save_state(&state)?;
+        started = true;
+        let result = match queued.request {
+            KinoRequest::Download { name, target, resume, content, save_as, on_collision, .. } => {
+                let local_name = save_as.unwrap_or_else(|| name.split("/").last().unwrap_or(&name).to_string());
+                let resolved = resolve_collision(files_dir, local_name.clone(), on_collision, || {
+                    remote_modified(&target, &name)
+                });
+                match resolved {
+                    Ok(Some(local_name)) => {
+                        let download = FileDownload { path: name, target, resume, content, local_name };
+                        start_download(our, files_dir, transfers, download)
+                    }
+                    Ok(None) => {
+                        println!("kino_files: keeping our {}, not downloading it", local_name);
+                        Request::new()
+                            .body(serde_json::to_vec(&KinoRequest::DownloadSkipped { name })?)
+                            .target(&target)
+                            .send()?;
+                        let event = TransferEvent {
+                            id: queued.id.to_string(),
+                            name: local_name,
+                            direction: Direction::Receive,
+                            kind: TransferEventKind::Skipped,
+                        };
+                        push_history(files_dir, HistoryEntry {
+                            id: event.id.clone(),
+                            peer: queued.peer.clone(),
+                            path: event.name.clone(),
+                            size: 0,
+                            direction: Direction::Receive,
+                            started: now(),
+                            ended: now(),
+                            outcome: TransferOutcome::Skipped,
+                            reason: None,
+                            hash: None,
+                        })?;
+                        update_folder(channel_id, transfers, &event)?;
+                        push_transfer_event_via_ws(channel_id, &event);
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            KinoRequest::SwarmDownload { name, target, peers, resume } => {
+                start_swarm_download(our, transfers, name, target, peers, resume)
+            }
+            other => Err(anyhow::anyhow!("can't start queued request {:?}", other)),
+        };
+        if let Err(e) = result {
+            println!("kino_files: failed to start queued download {}: {:?}", queued.id, e);
+            push_error_via_ws(channel_id, e.to_string());
+        }
+    }
+    if started {
+        let state = load_state();
+        push_queue_via_ws(channel_id, &state, transfers);
+    }
+    Ok(())
+}
+
+fn spawn_worker(our: &Address) -> anyhow::Result<Address> {
+    let our_worker = spawn(
+        None,
+        &format!("{}/pkg/worker.wasm", our.package_id()),
+        OnExit::None,
+        our_capabilities(),
+        vec![],
+        false,
+    )?;
+
+    Ok(Address {
+        node: our.node.clone(),
+        process: our_worker,
+    })
+}
+
 fn handle_kinofiles_request(
     our: &Address,
     source: &Address,
     body: &Vec<u8>,
     files_dir: &Directory,
     channel_id: &mut u32,
+    transfers: &mut Transfers,
+    hashing: &mut Hashing,
 ) -> anyhow::Result<()> {
     let Ok(kino_req) = serde_json::from_slice::<KinoRequest>(body) else {
         // println!("kino_files: error: failed to parse request: {}", String::from_utf8_lossy(&body));
@@ -156,89 +1685,411 @@ fn handle_kinofiles_request(
 
     match kino_req {
         KinoRequest::ListFiles => {
-            let files = ls_files(source, our, files_dir)?;
-            
             Response::new()
-                .body(serde_json::to_vec(&KinoResponse::ListFiles(files))?)
+                .body(serde_json::to_vec(&KinoResponse::ListFiles(ls_files(source, our, files_dir)?))?)
+                .send()?;
+        }
+        KinoRequest::ListPage(options) => {
+            let (files, next) = list_files(source, our, files_dir, &options)?;
+
+            Response::new()
+                .body(serde_json::to_vec(&KinoResponse::ListPage { files, next })?)
+                .send()?;
+        }
+        KinoRequest::Download { name: path, target, resume, content, save_as, dest, on_collision } => {
+            // spin up a worker, initialize based on whether it's a downloader or a sender.
+            match source.node == our.node {
+                true => {
+                    // we want to save a file to our node, once there's room for another worker.
+                    // collisions are settled when it starts, what's there then is what counts.
+                    let local_name = save_as.unwrap_or_else(|| path.split("/").last().unwrap_or(&path).to_string());
+                    enqueue(
+                        our,
+                        files_dir,
+                        transfers,
+                        channel_id,
+                        target.node.clone(),
+                        KinoRequest::Download {
+                            name: path,
+                            target,
+                            resume,
+                            content,
+                            save_as: Some(under_dest(&dest, &local_name)),
+                            dest: None,
+                            on_collision,
+                        },
+                    )?;
+                }
+                false => {
+                    // they want to save a file to their node
+                    let path = match content {
+                        Some(content) => match find_content(source, our, files_dir, &path, &content)? {
+                            Some(path) => {
+                                // an offer of it is used up all the same.
+                                claim_offer(channel_id, &source.node, &path)?;
+                                path
+                            }
+                            None => {
+                                println!("kino_files: no file with hash {} for node {}", content.hash, source.node);
+                                return Ok(())
+                            }
+                        },
+                        None => {
+                            // a file we offered them is theirs to download once. the offer is
+                            // used up by the download even if they could see the file anyway.
+                            let claimed = claim_offer(channel_id, &source.node, &path)?;
+                            if !claimed && !matches!(node_can_download_path(source, our, files_dir, &path), Ok(true)) {
+                                return Ok(())
+                            }
+                            path
+                        }
+                    };
+                    
+                    let our_worker_address = spawn_worker(our)?;
+                    let local_name = path.split("/files/").last().unwrap_or(&path);
+                    let state = load_state();
+                    transfers.insert(
+                        target.to_string(),
+                        ActiveTransfer {
+                            worker: our_worker_address.clone(),
+                            name: local_name.to_string(),
+                            peer: source.node.clone(),
+                            direction: Direction::Send,
+                            state: TransferState::Active,
+                            bytes: 0,
+                            rate: 0,
+                            started: now(),
+                            size: 0,
+                            hash: None,
+                        },
+                    );
+
+                    // a hash we already have spares the worker reading the whole file before it says hello.
+                    let hash = cached_content_id(&path).ok().flatten().map(|content| content.hash);
+                    Request::new()
+                        .body(serde_json::to_vec(&WorkerRequest::Initialize {
+                            name: local_name.to_string(),
+                            target_worker: Some(target),
+                            resume,
+                            hash,
+                            settings: state.settings,
+                        })?)
+                        .target(&our_worker_address)
+                        .send()?;
+                    apply_rate_limits(transfers)?;
+                }
+            }
+        }
+        KinoRequest::DownloadDir { name: path, target, resume, dest, on_collision } => {
+            if source.node != our.node {
+                return Ok(());
+            }
+            let download = DirDownload { path, target, resume, dest, on_collision };
+            download_dir(our, transfers, channel_id, files_dir, download)?;
+        }
+        KinoRequest::SwarmDownload { name: path, target, peers, resume } => {
+            if source.node != our.node {
+                return Ok(());
+            }
+            enqueue(
+                our,
+                files_dir,
+                transfers,
+                channel_id,
+                target.node.clone(),
+                KinoRequest::SwarmDownload { name: path, target, peers, resume },
+            )?;
+        }
+        KinoRequest::FileHash { path } => {
+            let visible = matches!(node_can_download_path(source, our, files_dir, &path), Ok(true));
+            let content = match visible {
+                true => cached_content_id(&path)?,
+                false => None,
+            };
+            // not hashed yet, a worker does it and answers them in our place. one already
+            // hashing the file answers this request too, and only so many hash at once.
+            let worker = match (visible, content.is_some(), hashing.get(&path)) {
+                (true, false, Some(worker)) => Some(worker.clone()),
+                (true, false, None) if hashing.len() < MAX_HASH_WORKERS => {
+                    let worker = spawn_worker(our)?;
+                    hashing.insert(path.clone(), worker.clone());
+                    Some(worker)
+                }
+                (true, false, None) => {
+                    println!("kino_files: already hashing {} files, not hashing {} for {}", hashing.len(), path, source.node);
+                    None
+                }
+                _ => None,
+            };
+            match worker {
+                Some(worker) => {
+                    Request::new()
+                        .body(serde_json::to_vec(&WorkerRequest::Hash { path })?)
+                        .target(&worker)
+                        .inherit(true)
+                        .send()?;
+                }
+                None => {
+                    Response::new()
+                        .body(serde_json::to_vec(&KinoResponse::FileHash(content))?)
+                        .send()?;
+                }
+            }
+        }
+        KinoRequest::Hashed { path, content } => {
+            if source.node != our.node {
+                return Ok(());
+            }
+            if let Some(content) = content {
+                cache_content_id(&path, content)?;
+            }
+            // anything we passed on to the worker before now is ahead of this in its queue, so it can go.
+            if hashing.get(&path) == Some(source) {
+                hashing.remove(&path);
+                Request::new()
+                    .body(serde_json::to_vec(&WorkerRequest::Exit)?)
+                    .target(source)
+                    .send()?;
+            }
+        }
+        KinoRequest::Modified { path } => {
+            let modified = match node_can_download_path(source, our, files_dir, &path) {
+                Ok(true) => modified(&path),
+                _ if offered(&source.node, &path) => modified(&path),
+                _ => None,
+            };
+            Response::new()
+                .body(serde_json::to_vec(&KinoResponse::Modified(modified))?)
+                .send()?;
+        }
+        KinoRequest::UpdateSettings(settings) => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("update settings request from non-local node"));
+            }
+            if settings.min_chunk_size == 0 || settings.min_chunk_size > settings.max_chunk_size {
+                return Err(anyhow::anyhow!("chunk size bounds must satisfy 0 < min <= max"));
+            }
+            if settings.max_upload_rate == Some(0) || settings.node_upload_rates.values().any(|&rate| rate == 0) {
+                return Err(anyhow::anyhow!("upload rate caps must be above 0"));
+            }
+            println!("kino_files: updating settings: {:?}", settings);
+            let mut state = load_state();
+            state.settings = settings;
+            save_state(&state)?;
+            push_state_via_ws(channel_id);
+            apply_rate_limits(transfers)?;
+        }
+        KinoRequest::Cancel { id, delete_partial } => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("cancel request from non-local node"));
+            }
+            send_to_transfer_worker(transfers, &id, &WorkerRequest::Cancel { delete_partial })?;
+        }
+        KinoRequest::Pause { id } => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("pause request from non-local node"));
+            }
+            send_to_transfer_worker(transfers, &id, &WorkerRequest::Pause)?;
+        }
+        KinoRequest::Resume { id } => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("resume request from non-local node"));
+            }
+            send_to_transfer_worker(transfers, &id, &WorkerRequest::Resume)?;
+        }
+        KinoRequest::UpdateQueueLimits(limits) => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("update queue limits request from non-local node"));
+            }
+            if limits.max_active == 0 || limits.max_per_peer == 0 {
+                return Err(anyhow::anyhow!("queue limits must be at least 1"));
+            }
+            println!("kino_files: updating queue limits: {:?}", limits);
+            let mut state = load_state();
+            state.queue_limits = limits;
+            save_state(&state)?;
+            push_queue_via_ws(channel_id, &state, transfers);
+            run_queue(our, files_dir, transfers, channel_id)?;
+        }
+        KinoRequest::Prioritize { id } => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("prioritize request from non-local node"));
+            }
+            let mut state = load_state();
+            let Some(index) = state.queue.iter().position(|queued| queued.id == id) else {
+                return Err(anyhow::anyhow!("prioritize: no queued download {}", id));
+            };
+            let queued = state.queue.remove(index);
+            state.queue.insert(0, queued);
+            save_state(&state)?;
+            push_queue_via_ws(channel_id, &state, transfers);
+            run_queue(our, files_dir, transfers, channel_id)?;
+        }
+        KinoRequest::Unqueue { id } => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("unqueue request from non-local node"));
+            }
+            let mut state = load_state();
+            state.queue.retain(|queued| queued.id != id);
+            save_state(&state)?;
+            push_queue_via_ws(channel_id, &state, transfers);
+        }
+        KinoRequest::TransferEvent(event) => {
+            if source.node != our.node {
+                return Ok(());
+            }
+            handle_transfer_event(our, files_dir, channel_id, transfers, event)?;
+        }
+        KinoRequest::Search(query) => {
+            let found = search_files(source, our, files_dir, &query)?;
+            Response::new()
+                .body(serde_json::to_vec(&KinoResponse::Search(found))?)
                 .send()?;
         }
-        KinoRequest::Download { name: path, target } => {
-            // spin up a worker, initialize based on whether it's a downloader or a sender.
-            let our_worker = spawn(
-                None,
-                &format!("{}/pkg/worker.wasm", our.package_id()),
-                OnExit::None,
-                our_capabilities(),
-                vec![],
-                false,
-            )?;
-
-            let our_worker_address = Address {
-                node: our.node.clone(),
-                process: our_worker,
+        KinoRequest::SearchContent { query } => {
+            let found = search_content(source, our, files_dir, &query)?;
+            Response::new()
+                .body(serde_json::to_vec(&KinoResponse::SearchContent(found))?)
+                .send()?;
+        }
+        KinoRequest::Thumbnail { path } => {
+            let png = match node_can_download_path(source, our, files_dir, &path) {
+                Ok(true) => match cached_thumbnail(files_dir, &path) {
+                    Some(png) => Some(png),
+                    None => match make_thumbnail(our, files_dir, &path, false) {
+                        // the worker answers them.
+                        Ok(()) => return Ok(()),
+                        Err(e) => {
+                            println!("kino_files: no thumbnail for {}: {:?}", path, e);
+                            None
+                        }
+                    },
+                },
+                _ => None,
             };
-
-            match source.node == our.node {
-                true => {
-                    // we want to save a file to our node
-                    let local_name = path.split("/").last().unwrap_or(&path);
-                    let _resp = Request::new()
-                        .body(serde_json::to_vec(&WorkerRequest::Initialize {
-                            name: local_name.to_string(),
-                            target_worker: None,
-                        })?)
-                        .target(&our_worker_address)
-                        .send_and_await_response(5)??;
-
-
-                    // send our initialized worker address to the other node
-                    Request::new()
-                        .body(serde_json::to_vec(&KinoRequest::Download {
-                            name: path.to_string(),
-                            target: our_worker_address,
-                        })?)
-                        .target(&target)
-                        .send()?;
-                }
+            let response = Response::new().body(serde_json::to_vec(&KinoResponse::Thumbnail(png.is_some()))?);
+            match png {
+                Some(png) => response.blob_bytes(png).send()?,
+                None => response.send()?,
+            }
+        }
+        KinoRequest::SetIndexing(enabled) => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("set indexing request from non-local node"));
+            }
+            let mut state = load_state();
+            state.indexing = enabled;
+            save_state(&state)?;
+            match enabled {
+                true => rebuild_index(our, files_dir)?,
                 false => {
-                    // they want to save a file to their node
-                    if let Ok(false) = node_can_download_path(source, our, files_dir, &path) {
-                        return Ok(())
-                    }
-                    
-                    let local_name = path.split("/files/").last().unwrap_or(&path);
-
-                    Request::new()
-                        .body(serde_json::to_vec(&WorkerRequest::Initialize {
-                            name: local_name.to_string(),
-                            target_worker: Some(target),
-                        })?)
-                        .target(&our_worker_address)
-                        .send()?;
+                    let _ = remove_file(&index_path(files_dir), None);
                 }
             }
+            push_state_via_ws(channel_id);
         }
-        KinoRequest::Progress { name, progress } => {
-            // print out in terminal and pipe to UI via websocket
-            println!("kino_files: file: {} progress: {}%", name, progress);
-            let ws_blob = LazyLoadBlob {
-                mime: Some("application/json".to_string()),
-                bytes: serde_json::json!({
-                    "kind": "progress",
-                    "data": {
-                        "name": name,
-                        "progress": progress,
-                    }
-                })
-                .to_string()
-                .as_bytes()
-                .to_vec(),
+        KinoRequest::ListTransfers => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("list transfers request from non-local node"));
+            }
+            Response::new()
+                .body(serde_json::to_vec(&KinoResponse::ListTransfers(list_transfers(transfers)))?)
+                .send()?;
+        }
+        KinoRequest::History(filter) => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("history request from non-local node"));
+            }
+            Response::new()
+                .body(serde_json::to_vec(&KinoResponse::History(query_history(files_dir, &filter)))?)
+                .send()?;
+        }
+        KinoRequest::Send { name, target } => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("send request from non-local node"));
+            }
+            send_offer(files_dir, channel_id, name, target)?;
+        }
+        KinoRequest::Offer { id, name, size } => {
+            if source.node == our.node {
+                return Ok(());
+            }
+            println!("kino_files: {} offers to send us {} ({} bytes)", source.node, name, size);
+            let mut state = load_state();
+            // the same offer again replaces the one we have.
+            state.inbox.retain(|offer| !(offer.from.node == source.node && offer.id == id));
+            if state.inbox.iter().filter(|offer| offer.from.node == source.node).count() >= MAX_OFFERS_PER_NODE {
+                println!("kino_files: inbox full for {}, declining {}", source.node, name);
+                Request::new()
+                    .body(serde_json::to_vec(&KinoRequest::OfferAnswered { id, accepted: false })?)
+                    .target(source)
+                    .send()?;
+                return Ok(());
+            }
+            state.inbox.push(IncomingOffer {
+                id,
+                from: source.clone(),
+                name,
+                size,
+                received: now(),
+            });
+            save_state(&state)?;
+            push_offers_via_ws(channel_id, &state);
+        }
+        KinoRequest::AcceptOffer { node, id, dest, on_collision } => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("accept offer request from non-local node"));
+            }
+            let offer = answer_offer(channel_id, &node, id, true)?;
+            let local_name = offer.name.split("/").last().unwrap_or(&offer.name).to_string();
+            enqueue(
+                our,
+                files_dir,
+                transfers,
+                channel_id,
+                offer.from.node.clone(),
+                KinoRequest::Download {
+                    name: offer.name,
+                    target: offer.from,
+                    resume: false,
+                    content: None,
+                    save_as: Some(under_dest(&dest, &local_name)),
+                    dest: None,
+                    on_collision,
+                },
+            )?;
+        }
+        KinoRequest::DeclineOffer { node, id } => {
+            if source.node != our.node {
+                return Err(anyhow::anyhow!("decline offer request from non-local node"));
+            }
+            answer_offer(channel_id, &node, id, false)?;
+        }
+        KinoRequest::OfferAnswered { id, accepted } => {
+            let mut state = load_state();
+            let Some(index) = state
+                .sent_offers
+                .iter()
+                .position(|offer| offer.node == source.node && offer.id == id)
+            else {
+                return Ok(());
             };
-            send_ws_push(
-                channel_id.clone(),
-                WsMessageType::Text,
-                ws_blob,
+            println!(
+                "kino_files: {} {} {}",
+                source.node,
+                if accepted { "accepted" } else { "declined" },
+                state.sent_offers[index].name
             );
+            if accepted {
+                state.sent_offers[index].accepted = true;
+            } else {
+                state.sent_offers.remove(index);
+            }
+            save_state(&state)?;
+            push_offers_via_ws(channel_id, &state);
+        }
+        KinoRequest::DownloadSkipped { name } => {
+            claim_offer(channel_id, &source.node, &name)?;
         }
         KinoRequest::Delete { name } => {
             if source.node != our.node {
@@ -247,9 +2098,12 @@ fn handle_kinofiles_request(
             println!("kino_files: deleting file: {}", name);
             let meta = metadata(&name, None)?;
             if meta.file_type == FileType::Directory {
+                let removed = files_under(&name);
                 remove_dir(&name, None)?;
+                files_changed(files_dir, &removed)?;
             } else {
                 remove_file(&name, None)?;
+                file_changed(files_dir, &name)?;
             }
             push_file_update_via_ws(channel_id);
         }
@@ -276,6 +2130,8 @@ fn handle_kinofiles_request(
             let dest_file = create_file(&dest_path, None)?;
             dest_file.write(&file.read()?)?;
             remove_file(&source_path, None)?;
+            file_changed(files_dir, &source_path)?;
+            file_changed(files_dir, &dest_path)?;
             push_file_update_via_ws(channel_id);
         }
         KinoRequest::ChangePermissions { path, perm } => {
@@ -283,8 +2139,7 @@ fn handle_kinofiles_request(
                 return Err(anyhow::anyhow!("permit path request from non-local node"));
             }
             println!("kino_files: changing perms for path: {}", path);
-            let mut state = get_typed_state(|bytes| Ok(serde_json::from_slice::<FileTransferState>(&bytes)?))
-                .unwrap_or(empty_state());
+            let mut state = load_state();
             match perm {
                 None => {
                     // println!("kino_files: removing all perms for file");
@@ -307,7 +2162,7 @@ fn handle_kinofiles_request(
                 state.known_nodes.push(source.node.clone());
             }
             // println!("kino_files: new perms: {:?}", state);
-            set_state(&serde_json::to_vec(&state)?);
+            save_state(&state)?;
             push_state_via_ws(channel_id);
             push_file_update_via_ws(channel_id);
         }
@@ -316,12 +2171,251 @@ fn handle_kinofiles_request(
     Ok(())
 }
 
+/// keep track of our transfers as their workers report in, and pass every event on to the UI.
+fn handle_transfer_event(
+    our: &Address,
+    files_dir: &Directory,
+    channel_id: &mut u32,
+    transfers: &mut Transfers,
+    event: TransferEvent,
+) -> anyhow::Result<()> {
+    let ended = match event.kind {
+        TransferEventKind::Started { size, ref hash } => {
+            if let Some(transfer) = transfers.get_mut(&event.id) {
+                transfer.size = size;
+                transfer.hash = Some(hash.clone());
+            }
+            // an upload's worker has just hashed our file, no need to do it again.
+            if event.direction == Direction::Send {
+                let path = format!("{}/{}", files_dir.path, event.name);
+                cache_content_id(&path, ContentId { size, hash: hash.clone() })?;
+            }
+            false
+        }
+        TransferEventKind::Progress { progress, rate, ref stats, .. } => {
+            println!(
+                "kino_files: file: {} progress: {}% at {} bytes/s ({:?}, {} bytes over the wire for {})",
+                event.name, progress, rate, stats.codec, stats.wire_bytes, stats.bytes
+            );
+            if let Some(transfer) = transfers.get_mut(&event.id) {
+                transfer.bytes = stats.bytes;
+                transfer.rate = rate;
+            }
+            false
+        }
+        TransferEventKind::Paused | TransferEventKind::Resumed => {
+            if let Some(transfer) = transfers.get_mut(&event.id) {
+                transfer.state = match event.kind {
+                    TransferEventKind::Paused => TransferState::Paused,
+                    _ => TransferState::Active,
+                };
+            }
+            false
+        }
+        TransferEventKind::Verifying => {
+            if let Some(transfer) = transfers.get_mut(&event.id) {
+                transfer.state = TransferState::Verifying;
+            }
+            false
+        }
+        TransferEventKind::Completed => {
+            println!("kino_files: file: {} {:?} completed", event.name, event.direction);
+            true
+        }
+        TransferEventKind::Failed { ref reason } => {
+            println!("kino_files: file: {} {:?} failed: {}", event.name, event.direction, reason);
+            true
+        }
+        TransferEventKind::Cancelled => true,
+        // skipped downloads never get a worker, run_queue reports them itself.
+        TransferEventKind::Queued | TransferEventKind::Skipped => false,
+    };
+
+    if ended {
+        if let Some(transfer) = transfers.remove(&event.id) {
+            record_history(files_dir, &transfer, &event)?;
+        }
+        // a download is staged until it's verified, only a completed one touches the file itself.
+        if event.direction == Direction::Receive && matches!(event.kind, TransferEventKind::Completed) {
+            file_changed(files_dir, &format!("{}/{}", files_dir.path, event.name))?;
+        }
+    }
+    if event.direction == Direction::Receive {
+        update_folder(channel_id, transfers, &event)?;
+    }
+    push_transfer_event_via_ws(channel_id, &event);
+    if ended {
+        push_file_update_via_ws(channel_id);
+        apply_rate_limits(transfers)?;
+        run_queue(our, files_dir, transfers, channel_id)?;
+    }
+    Ok(())
+}
+
+/// every transfer with one of our workers on it, oldest first, then the queue in order.
+fn list_transfers(transfers: &Transfers) -> Vec<TransferInfo> {
+    let mut active: Vec<(&String, &ActiveTransfer)> = transfers.iter().collect();
+    active.sort_by_key(|(_, transfer)| transfer.started);
+    let mut list: Vec<TransferInfo> = active
+        .into_iter()
+        .map(|(id, transfer)| TransferInfo {
+            id: id.clone(),
+            name: transfer.name.clone(),
+            peer: transfer.peer.clone(),
+            direction: transfer.direction,
+            bytes: transfer.bytes,
+            size: transfer.size,
+            rate: transfer.rate,
+            state: transfer.state,
+        })
+        .collect();
+
+    let state = load_state();
+    list.extend(state.queue.iter().map(|queued| TransferInfo {
+        id: queued.id.to_string(),
+        name: match &queued.request {
+            KinoRequest::Download { name, save_as, .. } => save_as.clone().unwrap_or_else(|| name.clone()),
+            KinoRequest::SwarmDownload { name, .. } => name.clone(),
+            _ => String::new(),
+        },
+        peer: queued.peer.clone(),
+        direction: Direction::Receive,
+        bytes: 0,
+        size: 0,
+        rate: 0,
+        state: TransferState::Queued,
+    }));
+    list
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0)
+}
+
+/// offer one of our files to target's kino_files, and let them download it if they accept.
+fn send_offer(files_dir: &Directory, channel_id: &mut u32, name: String, target: Address) -> anyhow::Result<()> {
+    if !name.trim_start_matches('/').starts_with(files_dir.path.trim_start_matches('/')) || is_internal_path(&name) {
+        return Err(anyhow::anyhow!("send: {} isn't one of our files", name));
+    }
+    let meta = metadata(&name, None)?;
+    if meta.file_type != FileType::File {
+        return Err(anyhow::anyhow!("send: {} isn't a file", name));
+    }
+    let mut state = load_state();
+    state.sent_offers.retain(|offer| !offer.expired());
+    let id = state.next_offer_id;
+    state.next_offer_id += 1;
+    state.sent_offers.push(SentOffer {
+        id,
+        node: target.node.clone(),
+        name: name.clone(),
+        size: meta.len,
+        sent: now(),
+        accepted: false,
+    });
+    save_state(&state)?;
+    println!("kino_files: offering {} to {}", name, target.node);
+    Request::new()
+        .body(serde_json::to_vec(&KinoRequest::Offer { id, name, size: meta.len })?)
+        .target(&target)
+        .send()?;
+    push_offers_via_ws(channel_id, &state);
+    Ok(())
+}
+
+/// take an offer out of our inbox and tell whoever made it what we decided.
+fn answer_offer(channel_id: &mut u32, node: &str, id: u64, accepted: bool) -> anyhow::Result<IncomingOffer> {
+    let mut state = load_state();
+    let Some(index) = state.inbox.iter().position(|offer| offer.from.node == node && offer.id == id) else {
+        return Err(anyhow::anyhow!("no offer {} from {}", id, node));
+    };
+    let offer = state.inbox.remove(index);
+    save_state(&state)?;
+    Request::new()
+        .body(serde_json::to_vec(&KinoRequest::OfferAnswered { id, accepted })?)
+        .target(&offer.from)
+        .send()?;
+    push_offers_via_ws(channel_id, &state);
+    Ok(offer)
+}
+
+fn offered(node: &str, path: &str) -> bool {
+    let state = load_state();
+    state
+        .sent_offers
+        .iter()
+        .any(|offer| offer.node == node && offer.name == path && !offer.expired())
+}
+
+/// use up our offer of path to node, if we made one and it hasn't expired.
+fn claim_offer(channel_id: &mut u32, node: &str, path: &str) -> anyhow::Result<bool> {
+    let mut state = load_state();
+    let Some(index) = state.sent_offers.iter().position(|offer| offer.node == node && offer.name == path) else {
+        return Ok(false);
+    };
+    // an expired one goes all the same, it's no good to anyone.
+    let offer = state.sent_offers.remove(index);
+    save_state(&state)?;
+    push_offers_via_ws(channel_id, &state);
+    Ok(!offer.expired())
+}
+
+fn record_history(files_dir: &Directory, transfer: &ActiveTransfer, event: &TransferEvent) -> anyhow::Result<()> {
+    let (outcome, reason) = match event.kind {
+        TransferEventKind::Completed => (TransferOutcome::Completed, None),
+        TransferEventKind::Failed { ref reason } => (TransferOutcome::Failed, Some(reason.clone())),
+        _ => (TransferOutcome::Cancelled, None),
+    };
+    push_history(files_dir, HistoryEntry {
+        id: event.id.clone(),
+        peer: transfer.peer.clone(),
+        path: transfer.name.clone(),
+        size: transfer.size,
+        direction: event.direction,
+        started: transfer.started,
+        ended: now(),
+        outcome,
+        reason,
+        hash: transfer.hash.clone(),
+    })
+}
+
+fn history_path(files_dir: &Directory) -> String {
+    let package = files_dir.path.rsplit_once('/').map_or("", |(package, _)| package);
+    format!("{}/{}/{}", package, HISTORY_DRIVE, HISTORY_FILE)
+}
+
+/// add entry to the end of the history file, leaving what's already there alone.
+fn push_history(files_dir: &Directory, entry: HistoryEntry) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(&entry)?;
+    line.push(b'\n');
+    open_file(&history_path(files_dir), true, None)?.append(&line)?;
+    Ok(())
+}
+
+fn query_history(files_dir: &Directory, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+    let Some(bytes) = open_file(&history_path(files_dir), false, None).ok().and_then(|file| file.read().ok()) else {
+        return vec![];
+    };
+    bytes
+        .split(|&byte| byte == b'\n')
+        .rev()
+        .filter_map(|line| serde_json::from_slice::<HistoryEntry>(line).ok())
+        .filter(|entry| filter.matches(entry))
+        .collect()
+}
+
 fn handle_http_request(
     our: &Address,
     source: &Address,
     body: &Vec<u8>,
     files_dir: &Directory,
     our_channel_id: &mut u32,
+    transfers: &mut Transfers,
+    hashing: &mut Hashing,
 ) -> anyhow::Result<()> {
     let http_request = serde_json::from_slice::<HttpServerRequest>(body)?;
 
@@ -329,6 +2423,118 @@ fn handle_http_request(
         HttpServerRequest::Http(request) => {
             match request.method()?.as_str() {
                 "GET" => {
+                    // files/queue -> queued downloads and the limits they wait on
+                    if request.path()?.ends_with("/files/queue") {
+                        let state = load_state();
+                        let mut headers = HashMap::new();
+                        headers.insert("Content-Type".to_string(), "application/json".to_string());
+                        let body = serde_json::to_vec(&queue_json(&state, transfers))?;
+                        send_response(StatusCode::OK, Some(headers), body);
+                        return Ok(());
+                    }
+                    // files/search?node=..&name=.. -> matching files on node, or on ours without it.
+                    // with content=.. it's their contents that are searched instead.
+                    if request.path()?.ends_with("/files/search") {
+                        let params = request.query_params();
+                        let response = match params.get("node") {
+                            Some(node) if *node != our.node => {
+                                let search = match params.get("content") {
+                                    Some(content) => KinoRequest::SearchContent { query: content.clone() },
+                                    None => KinoRequest::Search(SearchQuery::from_query(&params)),
+                                };
+                                let resp = Request::new()
+                                    .body(serde_json::to_vec(&search)?)
+                                    .target(&Address {
+                                        node: node.clone(),
+                                        process: our.process.clone(),
+                                    })
+                                    .send_and_await_response(5)??;
+                                match serde_json::from_slice::<KinoResponse>(resp.body())? {
+                                    found @ (KinoResponse::Search(_) | KinoResponse::SearchContent(_)) => found,
+                                    _ => return Err(anyhow::anyhow!("unexpected response to search")),
+                                }
+                            }
+                            _ => match params.get("content") {
+                                Some(content) => {
+                                    KinoResponse::SearchContent(search_content(source, our, files_dir, content)?)
+                                }
+                                None => KinoResponse::Search(search_files(
+                                    source,
+                                    our,
+                                    files_dir,
+                                    &SearchQuery::from_query(&params),
+                                )?),
+                            },
+                        };
+                        let mut headers = HashMap::new();
+                        headers.insert("Content-Type".to_string(), "application/json".to_string());
+                        let body = serde_json::to_vec(&response)?;
+                        send_response(StatusCode::OK, Some(headers), body);
+                        return Ok(());
+                    }
+                    // files/thumbnail?node=..&path=.. -> png thumbnail of an image on node, or on ours without it
+                    if request.path()?.ends_with("/files/thumbnail") {
+                        let params = request.query_params();
+                        let Some(path) = params.get("path") else {
+                            send_response(StatusCode::BAD_REQUEST, None, vec![]);
+                            return Ok(());
+                        };
+                        let png = match params.get("node") {
+                            Some(node) if *node != our.node => {
+                                let resp = Request::new()
+                                    .body(serde_json::to_vec(&KinoRequest::Thumbnail { path: path.clone() })?)
+                                    .target(&Address {
+                                        node: node.clone(),
+                                        process: our.process.clone(),
+                                    })
+                                    .send_and_await_response(5)??;
+                                match serde_json::from_slice::<KinoResponse>(resp.body())? {
+                                    KinoResponse::Thumbnail(true) => get_blob().map(|blob| blob.bytes),
+                                    _ => None,
+                                }
+                            }
+                            _ => match cached_thumbnail(files_dir, path) {
+                                Some(png) => Some(png),
+                                // the worker answers the http request.
+                                None if make_thumbnail(our, files_dir, path, true).is_ok() => return Ok(()),
+                                None => None,
+                            },
+                        };
+                        match png {
+                            Some(png) => {
+                                let mut headers = HashMap::new();
+                                headers.insert("Content-Type".to_string(), "image/png".to_string());
+                                send_response(StatusCode::OK, Some(headers), png);
+                            }
+                            None => send_response(StatusCode::NOT_FOUND, None, vec![]),
+                        }
+                        return Ok(());
+                    }
+                    // files/transfers -> what's transferring now, and what's queued
+                    if request.path()?.ends_with("/files/transfers") {
+                        if source.node != our.node {
+                            send_response(StatusCode::FORBIDDEN, None, vec![]);
+                            return Ok(());
+                        }
+                        let mut headers = HashMap::new();
+                        headers.insert("Content-Type".to_string(), "application/json".to_string());
+                        let body = serde_json::to_vec(&KinoResponse::ListTransfers(list_transfers(transfers)))?;
+                        send_response(StatusCode::OK, Some(headers), body);
+                        return Ok(());
+                    }
+                    // files/history?peer=..&direction=.. -> finished transfers, newest first
+                    if request.path()?.ends_with("/files/history") {
+                        if source.node != our.node {
+                            send_response(StatusCode::FORBIDDEN, None, vec![]);
+                            return Ok(());
+                        }
+                        let filter = HistoryFilter::from_query(&request.query_params());
+                        let mut headers = HashMap::new();
+                        headers.insert("Content-Type".to_string(), "application/json".to_string());
+                        let body = serde_json::to_vec(&KinoResponse::History(query_history(files_dir, &filter)))?;
+                        send_response(StatusCode::OK, Some(headers), body);
+                        return Ok(());
+                    }
                     // files?node=someoneelse.os -> get their files list
                     if let Some(remote_node) = request.query_params().get("node") {
                         let remote_node = Address {
@@ -336,23 +2542,28 @@ fn handle_http_request(
                             process: our.process.clone(),
                         };
 
-                        let state = get_typed_state(|bytes| Ok(serde_json::from_slice::<FileTransferState>(&bytes)?))
-                            .unwrap_or(empty_state());
+                        let state = load_state();
 
                         if !state.known_nodes.contains(&remote_node.node) {
                             let mut state = state;
                             state.known_nodes.push(remote_node.node.clone());
-                            set_state(&serde_json::to_vec(&state)?);
+                            save_state(&state)?;
                         }
                     
                         let resp = Request::new()
-                            .body(serde_json::to_vec(&KinoRequest::ListFiles)?)
+                            .body(serde_json::to_vec(&KinoRequest::ListPage(ListOptions::from_query(
+                                &request.query_params(),
+                            )))?)
                             .target(&remote_node)
                             .send_and_await_response(5)??;
 
                         handle_kinofiles_response(source, &resp.body().to_vec(), true)?;
-                    } else if let Some(path) = request.query_params().get("path") {
-                        // files?path=path/to/file -> send file contents to browser
+                    } else if let Some(path) = request
+                        .query_params()
+                        .get("path")
+                        .filter(|path| !metadata(path, None).is_ok_and(|meta| matches!(meta.file_type, FileType::Directory)))
+                    {
+                        // files?path=path/to/file -> send file contents to browser, a folder's path lists it below
                         
                         // you can only download your own files 
                         if source.node != our.node {
@@ -372,12 +2583,13 @@ fn handle_http_request(
                             send_response(StatusCode::NOT_FOUND, None, vec![]);
                         }
                     } else {
-                        // files -> list our files
-                        let files = ls_files(source, our, files_dir)?;
+                        // files?path=..&depth=..&limit=..&cursor=.. -> list our files
+                        let options = ListOptions::from_query(&request.query_params());
+                        let (files, next) = list_files(source, our, files_dir, &options)?;
                         let mut headers = HashMap::new();
                         headers.insert("Content-Type".to_string(), "application/json".to_string());
 
-                        let body = serde_json::to_vec(&KinoResponse::ListFiles(files))?;
+                        let body = serde_json::to_vec(&KinoResponse::ListPage { files, next })?;
 
                         send_response(StatusCode::OK, Some(headers), body);
                     }
@@ -407,6 +2619,16 @@ fn handle_http_request(
                         }
                     };
 
+                    // ?dest=folder&on_collision=Rename, same as a download.
+                    let dest = request.query_params().get("dest").cloned();
+                    let on_collision = match request.query_params().get("on_collision") {
+                        Some(policy) => serde_json::from_value::<CollisionPolicy>(
+                            serde_json::Value::String(policy.clone()),
+                        )
+                        .map_err(|_| anyhow::anyhow!("upload, unknown collision policy {}", policy))?,
+                        None => CollisionPolicy::default(),
+                    };
+
                     let data = Cursor::new(body.clone());
 
                     let mut multipart = multipart::server::Multipart::with_body(data, *boundary);
@@ -414,17 +2636,30 @@ fn handle_http_request(
                         if let Some(filename) = field.headers.filename.clone() {
                             let mut buffer = Vec::new();
                             field.data.read_to_end(&mut buffer)?;
-                            println!("kino_files: uploaded file {} with size {}", filename, buffer.len());
-                            let file_path = format!("{}/{}", files_dir.path, filename);
+                            // an upload is as new as it gets.
+                            let Some(local_name) = resolve_collision(
+                                files_dir,
+                                under_dest(&dest, &filename),
+                                on_collision,
+                                || Ok(Some(now())),
+                            )?
+                            else {
+                                println!("kino_files: keeping our {}, not replacing it with upload", filename);
+                                continue;
+                            };
+                            println!("kino_files: uploaded file {} with size {}", local_name, buffer.len());
+                            ensure_parent_dir(files_dir, &local_name)?;
+                            let file_path = format!("{}/{}", files_dir.path, local_name);
                             let file = create_file(&file_path, None)?;
                             file.write(&buffer)?;
+                            file_changed(files_dir, &file_path)?;
 
                             let ws_blob = LazyLoadBlob {
                                 mime: Some("application/json".to_string()),
                                 bytes: serde_json::json!({
                                     "kind": "uploaded",
                                     "data": {
-                                        "name": filename,
+                                        "name": local_name,
                                         "size": buffer.len(),
                                     }
                                 })
@@ -461,7 +2696,7 @@ fn handle_http_request(
             let Some(blob) = get_blob() else {
                 return Ok(());
             };
-            handle_kinofiles_request(our, source, &blob.bytes, files_dir, our_channel_id)?
+            handle_kinofiles_request(our, source, &blob.bytes, files_dir, our_channel_id, transfers, hashing)?
         }
     }
     Ok(())
@@ -475,8 +2710,101 @@ fn push_state_via_ws(channel_id: &mut u32) {
             mime: Some("application/json".to_string()),
             bytes: serde_json::json!({
                 "kind": "state",
-                "data": get_typed_state(|bytes| Ok(serde_json::from_slice::<FileTransferState>(&bytes)?))
-                    .unwrap_or(empty_state())
+                "data": load_state()
+            })
+            .to_string()
+            .as_bytes()
+            .to_vec()
+        }
+    )
+}
+
+fn queue_json(state: &FileTransferState, transfers: &Transfers) -> serde_json::Value {
+    serde_json::json!({
+        "queue": state.queue,
+        "limits": state.queue_limits,
+        "active": transfers.len(),
+    })
+}
+
+fn push_queue_via_ws(channel_id: &mut u32, state: &FileTransferState, transfers: &Transfers) {
+    send_ws_push(
+        channel_id.clone(),
+        WsMessageType::Text,
+        LazyLoadBlob {
+            mime: Some("application/json".to_string()),
+            bytes: serde_json::json!({
+                "kind": "queue",
+                "data": queue_json(state, transfers),
+            })
+            .to_string()
+            .as_bytes()
+            .to_vec()
+        }
+    )
+}
+
+fn push_offers_via_ws(channel_id: &mut u32, state: &FileTransferState) {
+    send_ws_push(
+        channel_id.clone(),
+        WsMessageType::Text,
+        LazyLoadBlob {
+            mime: Some("application/json".to_string()),
+            bytes: serde_json::json!({
+                "kind": "offers",
+                "data": {
+                    "inbox": state.inbox,
+                    "sent": state.sent_offers,
+                },
+            })
+            .to_string()
+            .as_bytes()
+            .to_vec()
+        }
+    )
+}
+
+fn push_transfer_event_via_ws(channel_id: &mut u32, event: &TransferEvent) {
+    send_ws_push(
+        channel_id.clone(),
+        WsMessageType::Text,
+        LazyLoadBlob {
+            mime: Some("application/json".to_string()),
+            bytes: serde_json::json!({
+                "kind": "transfer",
+                "data": event,
+            })
+            .to_string()
+            .as_bytes()
+            .to_vec()
+        }
+    )
+}
+
+fn push_folder_via_ws(channel_id: &mut u32, folder: &FolderDownload) {
+    let size: u64 = folder.files.values().map(|file| file.size).sum();
+    let bytes: u64 = folder.files.values().map(|file| file.bytes).sum();
+    send_ws_push(
+        channel_id.clone(),
+        WsMessageType::Text,
+        LazyLoadBlob {
+            mime: Some("application/json".to_string()),
+            bytes: serde_json::json!({
+                "kind": "folder",
+                "data": {
+                    "name": folder.name,
+                    "peer": folder.peer,
+                    "size": size,
+                    "bytes": bytes,
+                    "progress": if size == 0 { 100 } else { bytes * 100 / size },
+                    "files": folder.files.len(),
+                    "finished": folder.files.values().filter(|file| file.outcome.is_some()).count(),
+                    "failed": folder.files.values()
+                        .filter(|file| {
+                            matches!(file.outcome, Some(TransferOutcome::Failed | TransferOutcome::Cancelled))
+                        })
+                        .count(),
+                }
             })
             .to_string()
             .as_bytes()
@@ -526,14 +2854,14 @@ fn handle_kinofiles_response(_source: &Address, body: &Vec<u8>, is_http: bool) -
     };
 
     match kino_res {
-        KinoResponse::ListFiles(files) => {
+        KinoResponse::ListPage { files, next } => {
             // println!("kino_files: got files from node: {:?} ,files: {:?}", source, files);
 
             if is_http {
                 let mut headers = HashMap::new();
                 headers.insert("Content-Type".to_string(), "application/json".to_string());
 
-                let body = serde_json::to_vec(&KinoResponse::ListFiles(files))?;
+                let body = serde_json::to_vec(&KinoResponse::ListPage { files, next })?;
 
                 send_response(StatusCode::OK, Some(headers), body)
             }
@@ -548,8 +2876,24 @@ fn handle_message(
     our: &Address,
     files_dir: &Directory,
     channel_id: &mut u32,
+    transfers: &mut Transfers,
+    hashing: &mut Hashing,
 ) -> anyhow::Result<()> {
-    let message = await_message()?;
+    let message = match await_message() {
+        Ok(message) => message,
+        Err(send_error) => {
+            // a swarm download whose target never told us what it is.
+            let swarm = send_error
+                .context
+                .as_ref()
+                .and_then(|context| serde_json::from_slice::<SwarmContext>(context).ok());
+            if let Some(swarm) = swarm {
+                let reason = format!("{} didn't answer ({:?})", swarm.target.node, send_error.kind);
+                return abandon_swarm_download(channel_id, swarm, reason);
+            }
+            return Err(send_error.into());
+        }
+    };
 
     let http_server_address = ProcessId::from_str("http_server:distro:sys").unwrap();
 
@@ -557,17 +2901,24 @@ fn handle_message(
         Message::Response {
             ref source,
             ref body,
+            ref context,
             ..
-        } => handle_kinofiles_response(source, body, false),
+        } => match context
+            .as_ref()
+            .and_then(|context| serde_json::from_slice::<SwarmContext>(context).ok())
+        {
+            Some(swarm) => continue_swarm_download(channel_id, swarm, body),
+            None => handle_kinofiles_response(source, body, false),
+        },
         Message::Request {
             ref source,
             ref body,
             ..
         } => {
             if source.process == http_server_address {
-                handle_http_request(&our, source, body, files_dir, channel_id)?
+                handle_http_request(&our, source, body, files_dir, channel_id, transfers, hashing)?
             }
-            handle_kinofiles_request(&our, source, body, files_dir, channel_id)
+            handle_kinofiles_request(&our, source, body, files_dir, channel_id, transfers, hashing)
         }
     }
 }
@@ -576,12 +2927,59 @@ fn handle_message(
 struct FileTransferState {
     pub known_nodes: Vec<String>,
     pub permissions: HashMap<String, HashMap<String, bool>>,
+    // cached content hashes of our files, by path.
+    #[serde(default)]
+    pub content_ids: HashMap<String, ContentId>,
+    #[serde(default)]
+    pub settings: TransferSettings,
+    // downloads waiting to start, in the order they'll be started.
+    #[serde(default)]
+    pub queue: Vec<QueuedTransfer>,
+    #[serde(default)]
+    pub queue_limits: QueueLimits,
+    #[serde(default)]
+    pub next_queue_id: u64,
+    #[serde(default)]
+    pub folders: Vec<FolderDownload>,
+    // when we last wrote each of our files, by path. only what we've seen change.
+    #[serde(default)]
+    pub modified: HashMap<String, u64>,
+    // files peers offered us, and files we offered them.
+    #[serde(default)]
+    pub inbox: Vec<IncomingOffer>,
+    #[serde(default)]
+    pub sent_offers: Vec<SentOffer>,
+    #[serde(default)]
+    pub next_offer_id: u64,
+    // whether we keep a full-text index of our text files.
+    #[serde(default)]
+    pub indexing: bool,
+}
+
+fn load_state() -> FileTransferState {
+    get_typed_state(|bytes| Ok(serde_json::from_slice::<FileTransferState>(&bytes)?)).unwrap_or(empty_state())
+}
+
+fn save_state(state: &FileTransferState) -> anyhow::Result<()> {
+    set_state(&serde_json::to_vec(state)?);
+    Ok(())
 }
 
 fn empty_state() -> FileTransferState {
     FileTransferState {
         known_nodes: vec![],
         permissions: HashMap::new(),
+        content_ids: HashMap::new(),
+        settings: TransferSettings::default(),
+        queue: vec![],
+        queue_limits: QueueLimits::default(),
+        next_queue_id: 0,
+        folders: vec![],
+        modified: HashMap::new(),
+        inbox: vec![],
+        sent_offers: vec![],
+        next_offer_id: 0,
+        indexing: false,
     }
 }
 
@@ -590,8 +2988,9 @@ fn init(our: Address) {
     println!("kino_files: begin");
 
     let drive_path = create_drive(our.package_id(), "files", None).unwrap();
-    let state = get_typed_state(|bytes| Ok(serde_json::from_slice::<FileTransferState>(&bytes)?))
-        .unwrap_or(empty_state());
+    create_drive(our.package_id(), INDEX_DRIVE, None).unwrap();
+    create_drive(our.package_id(), HISTORY_DRIVE, None).unwrap();
+    let state = load_state();
     set_state(&serde_json::to_vec(&state).unwrap_or(vec![]));
     let files_dir = open_dir(&drive_path, false, None).unwrap();
 
@@ -614,12 +3013,24 @@ fn init(our: Address) {
 
     serve_ui(&our, &"ui", true, false, vec!["/"]).unwrap();
     bind_http_path("/files", false, false).unwrap();
+    bind_http_path("/files/queue", false, false).unwrap();
+    bind_http_path("/files/history", false, false).unwrap();
+    bind_http_path("/files/transfers", false, false).unwrap();
+    bind_http_path("/files/search", false, false).unwrap();
+    bind_http_path("/files/thumbnail", false, false).unwrap();
     bind_ws_path("/", false, false).unwrap();
 
     let mut channel_id: u32 = 1854;
+    let mut transfers: Transfers = HashMap::new();
+    let mut hashing: Hashing = HashMap::new();
+
+    // pick up downloads that were still queued when we stopped.
+    if let Err(e) = run_queue(&our, &files_dir, &mut transfers, &mut channel_id) {
+        println!("kino_files: failed to run queue: {:?}", e);
+    }
 
     loop {
-        match handle_message(&our, &files_dir, &mut channel_id) {
+        match handle_message(&our, &files_dir, &mut channel_id, &mut transfers, &mut hashing) {
             Ok(()) => {}
             Err(e) => {
                 print_to_terminal(2, format!("kino_files: error: {:?}", e).as_str());
@@ -628,3 +3039,62 @@ fn init(our: Address) {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_without_wildcards_is_a_substring() {
+        assert!(name_matches("port", "Report.md"));
+        assert!(!name_matches("port", "notes.md"));
+    }
+
+    #[test]
+    fn star_matches_any_run() {
+        assert!(name_matches("*.md", "notes.md"));
+        assert!(!name_matches("*.md", "notes.md.bak"));
+        assert!(name_matches("*", ""));
+    }
+
+    #[test]
+    fn star_backtracks() {
+        assert!(name_matches("*ab", "aab"));
+        assert!(name_matches("a*c", "abcbc"));
+        assert!(!name_matches("a*c", "abcb"));
+        assert!(name_matches("a*b*c", "AxxByyC"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(name_matches("file?.txt", "file1.txt"));
+        assert!(!name_matches("file?.txt", "file10.txt"));
+        assert!(!name_matches("?", ""));
+    }
+
+    #[test]
+    fn folder_entries_land_under_the_root() {
+        assert_eq!(local_path_in("pics", "copy", "pics"), Some("copy".to_string()));
+        assert_eq!(local_path_in("pics", "copy", "pics/a/b.png"), Some("copy/a/b.png".to_string()));
+    }
+
+    #[test]
+    fn entries_outside_the_folder_are_dropped() {
+        assert_eq!(local_path_in("pics", "copy", "picsx/b.png"), None);
+        assert_eq!(local_path_in("pics", "copy", "notes.md"), None);
+        assert_eq!(local_path_in("pics", "copy", "pics/../notes.md"), None);
+        assert_eq!(local_path_in("pics", "copy", "pics/./b.png"), None);
+        assert_eq!(local_path_in("pics", "copy", "pics//b.png"), None);
+        assert_eq!(local_path_in("pics", "copy", "pics/.transfers/b.part"), None);
+    }
+
+    #[test]
+    fn listed_count_covers_everything_under_a_folder() {
+        let file = |name: &str| KinoFileInfo { name: name.to_string(), ..Default::default() };
+        let inner = KinoFileInfo { dir: Some(vec![file("a/b/c")]), ..file("a/b") };
+        let folder = KinoFileInfo { dir: Some(vec![file("a/x"), inner]), ..file("a") };
+        assert_eq!(listed_count(&file("x")), 1);
+        assert_eq!(listed_count(&KinoFileInfo { dir: Some(vec![]), ..file("empty") }), 1);
+        assert_eq!(listed_count(&folder), 4);
+    }
+}
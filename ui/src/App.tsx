@@ -15,7 +15,7 @@ declare global {
 let inited = false
 
 function App() {
-  const { files, handleWsMessage, setApi, refreshFiles, permissionsModalOpen, } = useFileTransferStore();
+  const { files, handleWsMessage, setApi, refreshFiles, refreshTransfers, permissionsModalOpen, } = useFileTransferStore();
 
   const BASE_URL = import.meta.env.BASE_URL;
   const PROXY_TARGET = `${(import.meta.env.VITE_NODE_URL || "http://localhost:8080")}${BASE_URL}`;
@@ -42,6 +42,7 @@ function App() {
 
   useEffect(() => {
     refreshFiles()
+    refreshTransfers()
   }, [])
 
 
@@ -4,7 +4,7 @@ import useFileTransferStore from "../store/fileTransferStore";
 import classNames from "classnames";
 import { getReadableFilesize, trimBasePathFromPath, trimPathToFilename } from "../utils/file";
 import { FileIcon } from "./FileIcon";
-import { FaChevronDown, FaChevronRight, FaDownload, FaFolderPlus, FaLock, FaLockOpen, FaPlus, FaTrash, FaX } from "react-icons/fa6";
+import { FaChevronDown, FaChevronRight, FaDownload, FaFolderPlus, FaLock, FaLockOpen, FaPaperPlane, FaPause, FaPlay, FaPlus, FaTrash, FaX } from "react-icons/fa6";
 
 interface Props {
     file: KinoFile
@@ -14,15 +14,21 @@ interface Props {
     onToggleExpand?: () => void
 }
 function FileEntry({ file, node, isOurFile, expanded, onToggleExpand }: Props) {
-    const { filesInProgress, files, api, refreshFiles, onAddFolder, setEditingPermissionsForPath, setPermissionsModalOpen, permissions } = useFileTransferStore();
+    const { filesInProgress, files, api, refreshFiles, onAddFolder, onCancelTransfer, onPauseTransfer, onSendFile, pausedTransfers, folderProgress, setEditingPermissionsForPath, setPermissionsModalOpen, permissions } = useFileTransferStore();
     const [actualFileSize, setActualFileSize] = useState<string>('')
     const [isCreatingFolder, setIsCreatingFolder] = useState<boolean>(false)
     const [createdFolderName, setCreatedFolderName] = useState<string>('')
     const [savingToNode, setSavingToNode] = useState<boolean>(false)
     const [isDirectory, setIsDirectory] = useState<boolean>(false)
     const [showButtons, setShowButtons] = useState<boolean>(false)
+    const [noThumbnail, setNoThumbnail] = useState<boolean>(false)
+
+    // the node makes these for images, so we can see them without downloading.
+    const showThumbnail = !file.dir && !noThumbnail && !!file.mime?.match(/^image\/(png|jpeg|gif|webp|bmp)$/);
+    const thumbnailUrl = `${import.meta.env.BASE_URL}/files/thumbnail?node=${node}&path=${encodeURIComponent(file.name)}`
 
     const showSaveToNode = node !== window.our.node && !isDirectory;
+    const showSaveFolderToNode = node !== window.our.node && isDirectory;
 
     useEffect(() => {
         const directory = !!file.dir
@@ -30,7 +36,7 @@ function FileEntry({ file, node, isOurFile, expanded, onToggleExpand }: Props) {
     }, [file])
 
     useEffect(() => {
-        const fileSize = getReadableFilesize(file.size)
+        const fileSize = file.size === null ? '' : getReadableFilesize(file.size)
         setActualFileSize(fileSize);
     }, [file.size])
 
@@ -48,6 +54,19 @@ function FileEntry({ file, node, isOurFile, expanded, onToggleExpand }: Props) {
         })
     }
 
+    const onSaveFolderToNode = () => {
+        if (!file.name) return alert('No folder name');
+        if (!api) return alert('No api');
+        api.send({
+            data: {
+                DownloadDir: {
+                    name: file.name,
+                    target: `${node}@${window.our.process}`
+                }
+            }
+        })
+    }
+
     const onDelete = () => {
         if (!api) return alert('No api');
         if (!file.name || !trimPathToFilename(file.name)) return alert('No filename');
@@ -69,10 +88,12 @@ function FileEntry({ file, node, isOurFile, expanded, onToggleExpand }: Props) {
 
     const downloadInfo = Object.entries(filesInProgress).find(([key, _]) => file.name.match(key));
     const downloadInProgress = savingToNode || (downloadInfo?.[1] || 100) < 100;
+    const downloadPaused = !!downloadInfo && !!pausedTransfers[downloadInfo[0]];
     const downloadComplete = (
         (downloadInfo?.[1] || 0) === 100 ||
         (files.find(f => trimPathToFilename(f.name) === trimPathToFilename(file.name)) !== undefined)
     );
+    const folderDownloadProgress = folderProgress[trimPathToFilename(file.name)];
     const onFolderAdded = () => {
         onAddFolder(trimBasePathFromPath(file.name), createdFolderName, () => {
             setIsCreatingFolder(false);
@@ -84,6 +105,12 @@ function FileEntry({ file, node, isOurFile, expanded, onToggleExpand }: Props) {
         })
     };
 
+    const onSend = () => {
+        const recipient = window.prompt(`Which node should ${trimPathToFilename(file.name)} be offered to?`)
+        if (!recipient) return;
+        onSendFile(file.name, recipient)
+    }
+
     const onEditPermissions = () => {
         setEditingPermissionsForPath(file.name)
         setPermissionsModalOpen(true)
@@ -103,6 +130,12 @@ function FileEntry({ file, node, isOurFile, expanded, onToggleExpand }: Props) {
             })
     }
 
+    const details = [
+        file.mime,
+        file.modified && `modified ${new Date(file.modified * 1000).toLocaleString()}`,
+        file.hash && `sha256 ${file.hash}`,
+    ].filter(Boolean).join('\n')
+
     const fileHasSpecialPermissions = permissions
         && permissions[trimBasePathFromPath(file.name)]
         && Object.keys(permissions[trimBasePathFromPath(file.name)]).length > 0;
@@ -120,6 +153,7 @@ function FileEntry({ file, node, isOurFile, expanded, onToggleExpand }: Props) {
             <div className='flex flex-row justify-between place-items-center pr-1 relative'>
                 <div
                     className='flex whitespace-pre-wrap grow mr-1 items-center max-w-[20vw]'
+                    title={details || undefined}
                 >
                     {(file.dir) && <button
                         className="icon p-2 thin mr-4 -ml-4"
@@ -127,13 +161,22 @@ function FileEntry({ file, node, isOurFile, expanded, onToggleExpand }: Props) {
                     >
                         {expanded ? <FaChevronDown /> : <FaChevronRight />}
                     </button>}
-                    <FileIcon file={file} />
+                    {showThumbnail
+                        ? <img
+                            src={thumbnailUrl}
+                            loading='lazy'
+                            className='w-8 h-8 object-cover rounded mr-2'
+                            onError={() => setNoThumbnail(true)}
+                        />
+                        : <FileIcon file={file} />}
                     {trimPathToFilename(file.name)}
                     {file.dir && <span className='text-white text-sm px-2 py-1'>
-                        ({`${file.dir.length} ${file.dir.length === 1 ? 'file' : 'files'}`})
+                        ({`${file.children ?? file.dir.length} ${(file.children ?? file.dir.length) === 1 ? 'file' : 'files'}`})
                     </span>}
                 </div>
-                {!isDirectory && <span className="ml-auto">{actualFileSize || '0 KB'}</span>}
+                {!isDirectory
+                    ? <span className="ml-auto">{actualFileSize || '0 KB'}</span>
+                    : actualFileSize && <span className="ml-auto text-white text-sm">{actualFileSize}</span>}
                 {showSaveToNode && <button
                     disabled={isOurFile || downloadInProgress || downloadComplete}
                     className={classNames('px-2 py-0 ml-2', {
@@ -150,9 +193,32 @@ function FileEntry({ file, node, isOurFile, expanded, onToggleExpand }: Props) {
                         : downloadComplete
                             ? 'Saved'
                             : downloadInProgress
-                                ? <span>{downloadInfo?.[1] || 0}%</span>
+                                ? <span>{downloadInfo?.[1] || 0}%{downloadPaused && ' (paused)'}</span>
                                 : 'Save'}
                 </button>}
+                {showSaveFolderToNode && <button
+                    disabled={folderDownloadProgress !== undefined && folderDownloadProgress < 100}
+                    className='px-2 py-0 ml-2'
+                    onClick={onSaveFolderToNode}
+                >
+                    {folderDownloadProgress === undefined
+                        ? 'Save folder'
+                        : folderDownloadProgress < 100
+                            ? <span>{folderDownloadProgress}%</span>
+                            : 'Saved'}
+                </button>}
+                {showSaveToNode && downloadInProgress && downloadInfo && <button
+                    className='icon thin ml-2'
+                    onClick={() => onPauseTransfer(downloadInfo[0], !downloadPaused)}
+                >
+                    {downloadPaused ? <FaPlay /> : <FaPause />}
+                </button>}
+                {showSaveToNode && downloadInProgress && downloadInfo && <button
+                    className='icon thin ml-2'
+                    onClick={() => { onCancelTransfer(downloadInfo[0]); setSavingToNode(false) }}
+                >
+                    <FaX />
+                </button>}
                 {showButtons && isOurFile && !isCreatingFolder && <div className={classNames("absolute right-0 flex", { 'bg-orange': !isDirectory })}>
                     {isDirectory && <button
                         className={classNames('icon thin ml-2')}
@@ -166,6 +232,12 @@ function FileEntry({ file, node, isOurFile, expanded, onToggleExpand }: Props) {
                     >
                         <FaDownload />
                     </button>}
+                    {!isDirectory && <button
+                        className={classNames("icon thin ml-2")}
+                        onClick={onSend}
+                    >
+                        <FaPaperPlane />
+                    </button>}
                     <button
                         className={classNames('icon thin ml-2')}
                         onClick={onEditPermissions}
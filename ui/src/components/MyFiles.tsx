@@ -7,8 +7,8 @@ import SortableTree, { TreeItem } from '@nosferatu500/react-sortable-tree';
 import FileExplorerTheme from '@nosferatu500/theme-file-explorer';
 import FileEntry from './FileEntry';
 import { TreeFile } from '../types/TreeFile';
-import { trimPathToRootDir } from '../utils/file';
-import { FaChevronDown, FaChevronUp, FaFolderPlus, FaX } from 'react-icons/fa6';
+import { getReadableFilesize, trimPathToFilename, trimPathToRootDir } from '../utils/file';
+import { FaCheck, FaChevronDown, FaChevronUp, FaFolderPlus, FaX } from 'react-icons/fa6';
 import classNames from 'classnames';
 
 interface Props {
@@ -17,7 +17,7 @@ interface Props {
 }
 
 const MyFiles = ({ files, node }: Props) => {    
-    const { onAddFolder, onMoveFile, refreshFiles, errors, setErrors, clearErrors } = useFileTransferStore();
+    const { onAddFolder, onMoveFile, refreshFiles, errors, setErrors, clearErrors, offers, onAnswerOffer } = useFileTransferStore();
     const [createdFolderName, setCreatedFolderName] = useState<string>('')
     const [isCreatingFolder, setIsCreatingFolder] = useState<boolean>(false)
     const [treeData, setTreeData] = useState<TreeItem[]>([])
@@ -157,6 +157,28 @@ const MyFiles = ({ files, node }: Props) => {
                     />
                 }
             </div>
+            {offers.inbox.length > 0 && <div className='flex flex-col p-1'>
+                <span className='heading px-2 py-1'>Offered to you</span>
+                {offers.inbox.map(offer => <span key={`${offer.from}-${offer.id}`}
+                    className='px-2 py-1 flex place-items-center'
+                >
+                    <span className='flex-grow'>
+                        {offer.from.split('@')[0]}: {trimPathToFilename(offer.name)} ({getReadableFilesize(offer.size)})
+                    </span>
+                    <button
+                        className='icon thin ml-2'
+                        onClick={() => onAnswerOffer(offer, true)}
+                    >
+                        <FaCheck />
+                    </button>
+                    <button
+                        className='icon thin ml-2'
+                        onClick={() => onAnswerOffer(offer, false)}
+                    >
+                        <FaX />
+                    </button>
+                </span>)}
+            </div>}
             <div className={classNames('flex flex-col bg-red-500/50 p-1', { hidden: errors.length === 0 })}>
                 {errors.map((error, i) => <span key={i} 
                     className='px-2 py-1 flex place-items-center'
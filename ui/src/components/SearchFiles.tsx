@@ -5,10 +5,13 @@ import useFileTransferStore from '../store/fileTransferStore';
 import SortableTree, { TreeItem } from '@nosferatu500/react-sortable-tree';
 import FileExplorerTheme from '@nosferatu500/theme-file-explorer';
 import { FaChevronDown, FaChevronUp, FaMagnifyingGlass } from 'react-icons/fa6';
+import { fetchFileList, findFile, replaceChildren } from '../utils/file';
 
 const SearchFiles = function() {
     const { knownNodes, setKnownNodes } = useFileTransferStore();
     const [searchTerm, setSearchTerm] = useState('');
+    const [nameFilter, setNameFilter] = useState('');
+    const [contentFilter, setContentFilter] = useState('');
     const [foundFiles, setFoundFiles] = useState<KinoFile[] | undefined>();
     const [searching, setSearching] = useState<boolean>(false);
     const [expandedFiles, setExpandedFiles] = useState<{ [path:string]: boolean }>({})
@@ -20,28 +23,33 @@ const SearchFiles = function() {
         if (searching) return
         setKnownNodes([...knownNodes, searchTerm].filter((v, i, a) => a.indexOf(v) === i));
         setSearching(true);
-        try {
-            fetch(`${import.meta.env.BASE_URL}/files?node=${searchTerm}`, {
-                method: 'GET',
-                headers: {
-                    'Content-Type': 'application/json',
-                },
-            }).then((response) => response.json())
+        // just the top level, folders are fetched as they're opened.
+        // with a name to look for, the node searches its whole tree for us.
+        // words to look for are matched against the node's content index instead.
+        const request = contentFilter
+            ? fetch(`${import.meta.env.BASE_URL}/files/search?node=${searchTerm}&content=${encodeURIComponent(contentFilter)}`)
+                .then((response) => response.json())
+                .then((data) => data.SearchContent as KinoFile[])
+            : nameFilter
+            ? fetch(`${import.meta.env.BASE_URL}/files/search?node=${searchTerm}&name=${encodeURIComponent(nameFilter)}`)
+                .then((response) => response.json())
+                .then((data) => data.Search as KinoFile[])
+            : fetchFileList(`node=${searchTerm}&depth=1`)
+        request
+            .then((files) => {
+                setFoundFiles(files)
+                setSearching(false);
+            })
             .catch(() => {
                 window.alert(`${searchTerm} appears to be offline, or has not installed Kino Files.`)
                 setSearching(false);
             })
-            .then((data) => {
-                try {
-                    setFoundFiles(data.ListFiles)
-                    setSearching(false);
-                } catch {
-                    console.log("Failed to parse JSON files", data);
-                }
-            });
-        } catch (error) {
-            console.error('Error:', error);
-        }
+    };
+
+    const loadFolder = (name: string) => {
+        fetchFileList(`node=${searchTerm}&path=${encodeURIComponent(name)}&depth=1`)
+            .then((children) => setFoundFiles((prev) => prev && replaceChildren(prev, name, children)))
+            .catch(() => window.alert(`Failed to list ${name} on ${searchTerm}.`))
     };
 
     const treeifyFile: (node: string, f: KinoFile) => TreeItem = (node: string, file: KinoFile) => {
@@ -61,6 +69,7 @@ const SearchFiles = function() {
 
     const toggleExpandedForOne = (path: string, expanded: boolean) => {
         setExpandedFiles((prev) => ({ ...prev, [path]: expanded }));
+        if (expanded && foundFiles && findFile(foundFiles, path)?.unlisted) loadFolder(path)
     }
 
     const expand = (expanded: boolean) => {
@@ -96,6 +105,24 @@ const SearchFiles = function() {
                         <FaMagnifyingGlass />
                     </button>
                 </div>
+                <input
+                    className='ml-2 grow'
+                    type="text"
+                    value={nameFilter}
+                    placeholder='file name or *.glob (optional)'
+                    disabled={searching}
+                    onChange={(e) => setNameFilter(e.target.value)}
+                    onKeyUp={(e) => e.key === 'Enter' && handleSearch()}
+                />
+                <input
+                    className='ml-2 grow'
+                    type="text"
+                    value={contentFilter}
+                    placeholder='words in the file (optional)'
+                    disabled={searching}
+                    onChange={(e) => setContentFilter(e.target.value)}
+                    onKeyUp={(e) => e.key === 'Enter' && handleSearch()}
+                />
                 {knownNodes.length > 0 && <div 
                     className='flex grow place-items-center'
                 >
@@ -147,9 +174,7 @@ const SearchFiles = function() {
                             treeData={treeData}
                             onChange={treeData => setTreeData([...treeData])}
                             getNodeKey={({ node }: { node: TreeItem }) => node.file.name}
-                            onVisibilityToggle={({ expanded, node }) => {
-                                setExpandedFiles((prev) => ({ ...prev, [node?.file?.name]: expanded }))
-                            }}
+                            onVisibilityToggle={({ expanded, node }) => toggleExpandedForOne(node?.file?.name, expanded)}
                             canDrag={() => false}
                             canDrop={() => false}
                         />
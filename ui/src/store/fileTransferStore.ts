@@ -3,7 +3,7 @@ import { persist, createJSONStorage } from 'zustand/middleware'
 import KinoFile from '../types/KinoFile'
 import KinodeApi from '@kinode/client-api'
 import { TreeFile } from '../types/TreeFile'
-import { trimPathToParentFolder } from '../utils/file'
+import { fetchFileList, trimPathToParentFolder } from '../utils/file'
 import { Permissions } from '../types/Permissions'
 
 export interface FileTransferStore {
@@ -13,9 +13,19 @@ export interface FileTransferStore {
   set: (partial: FileTransferStore | Partial<FileTransferStore>) => void
   filesInProgress: { [key: string]: number }
   setFilesInProgress: (filesInProgress: { [key: string]: number }) => void
+  transferIds: { [name: string]: string }
+  onCancelTransfer: (name: string) => void
+  pausedTransfers: { [name: string]: boolean }
+  onPauseTransfer: (name: string, paused: boolean) => void
+  queue: TransferQueue
+  folderProgress: { [name: string]: number }
+  offers: Offers
+  onSendFile: (name: string, node: string) => void
+  onAnswerOffer: (offer: IncomingOffer, accept: boolean) => void
   api: KinodeApi | null
   setApi: (api: KinodeApi) => void
   refreshFiles: () => void
+  refreshTransfers: () => void
   knownNodes: string[]
   setKnownNodes: (knownNodes: string[]) => void
   onAddFolder: (root: string, createdFolderName: string, callback: () => void) => void
@@ -32,11 +42,78 @@ export interface FileTransferStore {
   setPermissions: (permissions: Permissions) => void
 }
 
+export interface TransferStats {
+  codec: 'None' | 'Gzip'
+  bytes: number
+  wire_bytes: number
+}
+
+export type TransferEventKind =
+  | 'Queued' | 'Paused' | 'Resumed' | 'Verifying' | 'Completed' | 'Cancelled' | 'Skipped'
+  | { Started: { size: number } }
+  | { Progress: { size: number, progress: number, rate: number, eta: number | null, stats: TransferStats } }
+  | { Failed: { reason: string } }
+
+export interface TransferEvent {
+  id: string
+  name: string
+  direction: 'Send' | 'Receive'
+  kind: TransferEventKind
+}
+
+export interface QueuedTransfer {
+  id: number
+  peer: string
+  request: any
+}
+
+export interface TransferQueue {
+  queue: QueuedTransfer[]
+  limits: { max_active: number, max_per_peer: number }
+  active: number
+}
+
+export interface TransferInfo {
+  id: string
+  name: string
+  peer: string
+  direction: 'Send' | 'Receive'
+  bytes: number
+  size: number
+  rate: number
+  state: 'Queued' | 'Active' | 'Paused' | 'Verifying'
+}
+
+export interface IncomingOffer {
+  id: number
+  from: string
+  name: string
+  size: number
+  received: number
+}
+
+export interface SentOffer {
+  id: number
+  node: string
+  name: string
+  size: number
+  sent: number
+  accepted: boolean
+}
+
+export interface Offers {
+  inbox: IncomingOffer[]
+  sent: SentOffer[]
+}
+
 type WsMessage =
-  | { kind: 'progress', data: { name: string, progress: number } }
+  | { kind: 'transfer', data: TransferEvent }
+  | { kind: 'queue', data: TransferQueue }
+  | { kind: 'offers', data: Offers }
+  | { kind: 'folder', data: { name: string, peer: string, size: number, bytes: number, progress: number, files: number, finished: number, failed: number } }
   | { kind: 'uploaded', data: { name: string, size: number } }
   | { kind: 'file_update', data: { path: string } }
-  | { kind: 'state', data: { known_nodes: string[], permissions: Permissions } }
+  | { kind: 'state', data: { known_nodes: string[], permissions: Permissions, inbox: IncomingOffer[], sent_offers: SentOffer[] } }
   | { kind: 'error', data: string }
 
 const useFileTransferStore = create<FileTransferStore>()(
@@ -44,6 +121,11 @@ const useFileTransferStore = create<FileTransferStore>()(
     (set, get) => ({
       files: [],
       filesInProgress: {},
+      transferIds: {},
+      pausedTransfers: {},
+      queue: { queue: [], limits: { max_active: 4, max_per_peer: 2 }, active: 0 },
+      folderProgress: {},
+      offers: { inbox: [], sent: [] },
       knownNodes: [],
       errors: [],
       clearErrors: () => set({ errors: [] }),
@@ -60,27 +142,56 @@ const useFileTransferStore = create<FileTransferStore>()(
       setPermissions: (permissions: Permissions) => set({ permissions }),
       setFiles: (files) => set({ files }),    
       handleWsMessage: (json: string | Blob) => {
-        const { setPermissions, filesInProgress, setFilesInProgress, setKnownNodes, refreshFiles, setErrors, errors } = get()
+        const { setPermissions, filesInProgress, setFilesInProgress, transferIds, pausedTransfers, setKnownNodes, refreshFiles, setErrors, errors } = get()
         if (typeof json === 'string') {
           try {
             console.log('WS: GOT MESSAGE', json)
             const { kind, data } = JSON.parse(json) as WsMessage;
-            if (kind === 'progress') {
-              const { name, progress } = data
-              const fip = { ...filesInProgress, [name]: progress }
-              console.log({ fip })
-              setFilesInProgress(fip)
-              if (progress >= 100) {
-                get().refreshFiles()
+            if (kind === 'transfer') {
+              const { id, name, direction, kind: event } = data
+              // the UI follows our downloads, a file we're sending keeps its own entry
+              if (direction !== 'Receive') return
+              const { [name]: _progress, ...fip } = filesInProgress
+              const { [name]: _id, ...ids } = transferIds
+              if (typeof event === 'object' && 'Progress' in event) {
+                const { progress } = event.Progress
+                setFilesInProgress({ ...filesInProgress, [name]: progress })
+                set({ transferIds: { ...transferIds, [name]: id } })
+              } else if (typeof event === 'object' && 'Failed' in event) {
+                setFilesInProgress(fip)
+                set({ transferIds: ids })
+                setErrors([...errors, `${name} failed: ${event.Failed.reason}`])
+              } else if (event === 'Cancelled' || event === 'Skipped') {
+                setFilesInProgress(fip)
+                set({ transferIds: ids })
+              } else if (event === 'Completed') {
+                setFilesInProgress({ ...filesInProgress, [name]: 100 })
+                refreshFiles()
+              } else if (event === 'Paused' || event === 'Resumed') {
+                set({
+                  pausedTransfers: { ...pausedTransfers, [name]: event === 'Paused' },
+                  transferIds: { ...transferIds, [name]: id },
+                })
+              }
+            } else if (kind === 'queue') {
+              set({ queue: data })
+            } else if (kind === 'offers') {
+              set({ offers: data })
+            } else if (kind === 'folder') {
+              const { name, progress, files, finished, failed } = data
+              set({ folderProgress: { ...get().folderProgress, [name]: finished === files ? 100 : progress } })
+              if (finished === files && failed > 0) {
+                setErrors([...errors, `${failed} of ${files} files in ${name} failed to download.`])
               }
             } else if (kind === 'uploaded') {
               refreshFiles()
             } else if (kind === 'file_update') {
               refreshFiles()
             } else if (kind === 'state') {
-              const { known_nodes, permissions } = data
+              const { known_nodes, permissions, inbox, sent_offers } = data
               setKnownNodes(known_nodes)
               setPermissions(permissions)
+              set({ offers: { inbox: inbox || [], sent: sent_offers || [] } })
             } else if (kind === 'error') {
               console.log({ error: data })
               setErrors([...errors, data])
@@ -130,6 +241,39 @@ const useFileTransferStore = create<FileTransferStore>()(
 
         setTimeout(() => refreshFiles(), 1000);
       },
+      onCancelTransfer: (name: string) => {
+        const { api, transferIds } = get()
+        if (!api) return alert('No API');
+        const id = transferIds[name]
+        if (!id) return alert('No transfer in progress');
+        if (!window.confirm(`Are you sure you want to cancel the transfer of ${name}?`)) return;
+        const delete_partial = window.confirm('Delete the partially downloaded file? Cancel to keep it for resuming later.')
+
+        api.send({ data: { Cancel: { id, delete_partial } } })
+      },
+      onPauseTransfer: (name: string, paused: boolean) => {
+        const { api, transferIds } = get()
+        if (!api) return alert('No API');
+        const id = transferIds[name]
+        if (!id) return alert('No transfer in progress');
+
+        api.send({ data: paused ? { Pause: { id } } : { Resume: { id } } })
+      },
+      onSendFile: (name: string, node: string) => {
+        const { api } = get()
+        if (!api) return alert('No API');
+        if (!name) return alert('No file name');
+        if (!node) return alert('No node');
+
+        api.send({ data: { Send: { name, target: `${node}@${window.our.process}` } } })
+      },
+      onAnswerOffer: (offer: IncomingOffer, accept: boolean) => {
+        const { api } = get()
+        if (!api) return alert('No API');
+        const node = offer.from.split('@')[0]
+
+        api.send({ data: accept ? { AcceptOffer: { node, id: offer.id } } : { DeclineOffer: { node, id: offer.id } } })
+      },
       onChangePermissionsForNode: (path: string, perm?: { node: string, allow?: boolean }) => {
         const { api, refreshFiles } = get()
         console.log('changing node access to file', path, perm);
@@ -150,19 +294,34 @@ const useFileTransferStore = create<FileTransferStore>()(
       refreshFiles: () => {
         const { setFiles, setErrors, errors } = get()
         console.log('refreshing files')
-        fetch(`${import.meta.env.BASE_URL}/files`)
-          .then((response) => response.json())
-          .then((data) => {
-            try {
-              setFiles(data.ListFiles)
-            } catch {
-              console.log("Failed to parse JSON files", data);
-            }
-          })
+        fetchFileList('')
+          .then((files) => setFiles(files))
           .catch(() => {
             setErrors([...errors, 'Failed to fetch files. Please check your connection.'])
           })
       },
+      refreshTransfers: () => {
+        // picks up downloads that were already running before a reload.
+        fetch(`${import.meta.env.BASE_URL}/files/transfers`)
+          .then((response) => response.json())
+          .then((data) => {
+            const { filesInProgress, transferIds, pausedTransfers } = get()
+            const running = (data.ListTransfers as TransferInfo[])
+              .filter(({ direction, state }) => direction === 'Receive' && state !== 'Queued')
+            set({
+              filesInProgress: {
+                ...filesInProgress,
+                ...Object.fromEntries(running.map(({ name, bytes, size }) => [name, size ? Math.floor(bytes * 100 / size) : 0])),
+              },
+              transferIds: { ...transferIds, ...Object.fromEntries(running.map(({ name, id }) => [name, id])) },
+              pausedTransfers: {
+                ...pausedTransfers,
+                ...Object.fromEntries(running.map(({ name, state }) => [name, state === 'Paused'])),
+              },
+            })
+          })
+          .catch(() => console.log('Failed to fetch transfers'))
+      },
       set,
       get,
     }),
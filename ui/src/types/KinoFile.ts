@@ -1,8 +1,17 @@
 
 interface KinoFile {
     name: string,
-    size: number,
+    // null for a folder when some of what's in it wasn't listed
+    size: number | null,
     dir?: KinoFile[],
+    // a folder whose contents haven't been fetched yet
+    unlisted?: boolean,
+    // seconds since the epoch, if the node knows
+    modified?: number | null,
+    mime?: string | null,
+    hash?: string | null,
+    // entries in a folder
+    children?: number | null,
 }
 
 export default KinoFile;
\ No newline at end of file
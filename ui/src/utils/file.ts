@@ -1,3 +1,5 @@
+import KinoFile from '../types/KinoFile'
+
 export const trimPathToFilename = (filename: string) => {
     return filename.split('/').pop() || '';
 }
@@ -23,3 +25,33 @@ export const getReadableFilesize = (size: number) => size > 1000000000000
     : size === 0
     ? ''
     : `${(size / 1000).toFixed(2)} KB`;
+
+// every page of a /files listing, query being the rest of its query string
+export const fetchFileList = async (query: string): Promise<KinoFile[]> => {
+    const files: KinoFile[] = []
+    let cursor: string | null = null
+    do {
+        const params = new URLSearchParams(query)
+        if (cursor) params.set('cursor', cursor)
+        const response = await fetch(`${import.meta.env.BASE_URL}/files?${params}`)
+        const { ListPage: { files: page, next } } = await response.json()
+        files.push(...page)
+        cursor = next
+    } while (cursor)
+    return files
+}
+
+export const findFile = (files: KinoFile[], name: string): KinoFile | undefined => {
+    for (const file of files) {
+        if (file.name === name) return file
+        const found = file.dir && findFile(file.dir, name)
+        if (found) return found
+    }
+}
+
+export const replaceChildren = (files: KinoFile[], name: string, children: KinoFile[]): KinoFile[] =>
+    files.map(file => file.name === name
+        ? { ...file, dir: children, unlisted: false }
+        : file.dir
+        ? { ...file, dir: replaceChildren(file.dir, name, children) }
+        : file)
@@ -0,0 +1,77 @@
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// how a chunk's bytes are encoded on the wire.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub enum Codec {
+    #[default]
+    None,
+    Gzip,
+}
+
+/// codecs this worker can handle, in order of preference.
+pub const SUPPORTED_CODECS: [Codec; 1] = [Codec::Gzip];
+
+pub fn compress(codec: Codec, bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+    match codec {
+        Codec::None => Ok(bytes.to_vec()),
+        Codec::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+            encoder.write_all(bytes)?;
+            Ok(encoder.finish()?)
+        }
+    }
+}
+
+/// decode a chunk that should come out at length bytes. anything longer is refused
+/// before it's fully inflated, so a sender can't make us decompress a bomb.
+pub fn decompress(codec: Codec, bytes: Vec<u8>, length: u64) -> anyhow::Result<Vec<u8>> {
+    match codec {
+        Codec::None => Ok(bytes),
+        Codec::Gzip => {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(&bytes[..])
+                .take(length + 1)
+                .read_to_end(&mut decompressed)?;
+            if decompressed.len() as u64 > length {
+                return Err(anyhow::anyhow!("chunk inflates past its declared {} bytes", length));
+            }
+            Ok(decompressed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gzip_round_trips() {
+        let bytes = b"kino files kino files kino files".repeat(100);
+        let compressed = compress(Codec::Gzip, &bytes).unwrap();
+        assert!(compressed.len() < bytes.len());
+        let length = bytes.len() as u64;
+        assert_eq!(decompress(Codec::Gzip, compressed, length).unwrap(), bytes);
+    }
+
+    #[test]
+    fn none_passes_bytes_through() {
+        let bytes = b"as is".to_vec();
+        assert_eq!(compress(Codec::None, &bytes).unwrap(), bytes);
+        assert_eq!(decompress(Codec::None, bytes.clone(), 5).unwrap(), bytes);
+    }
+
+    #[test]
+    fn inflating_past_length_is_refused() {
+        // a megabyte of zeros packs into a few hundred bytes.
+        let compressed = compress(Codec::Gzip, &vec![0; 1048576]).unwrap();
+        assert!(decompress(Codec::Gzip, compressed.clone(), 1024).is_err());
+        assert!(decompress(Codec::Gzip, compressed, 1048575).is_err());
+    }
+
+    #[test]
+    fn garbage_is_refused() {
+        assert!(decompress(Codec::Gzip, b"not gzip".to_vec(), 8).is_err());
+    }
+}
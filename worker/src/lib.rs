@@ -1,12 +1,28 @@
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Cursor;
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 use kinode_process_lib::{
-    await_message, get_blob, println,
-    vfs::{open_dir, open_file, Directory, File, SeekFrom},
-    Address, Message, ProcessId, Request, Response,
+    await_message, get_blob,
+    http::{send_response, StatusCode},
+    println, timer,
+    vfs::{
+        create_file, open_dir, open_file, remove_file, Directory, File, SeekFrom, VfsAction,
+        VfsRequest, VfsResponse,
+    },
+    Address, Message, ProcessId, Request, Response, SendError,
 };
 
+mod codec;
+use codec::{compress, decompress, Codec, SUPPORTED_CODECS};
+mod ranges;
+use ranges::Ranges;
+mod throttle;
+use throttle::Throttle;
+
 wit_bindgen::generate!({
     path: "wit",
     world: "process",
@@ -15,89 +31,974 @@ wit_bindgen::generate!({
     },
 });
 
+// starting chunk size, the sender adapts it from there.
 const CHUNK_SIZE: u64 = 1048576; // 1MB
+// the sender grows chunks while acks take less than half this, and shrinks them past it.
+const TARGET_RTT: Duration = Duration::from_secs(2);
+const MAX_RESENDS: u32 = 3;
+// chunks the sender keeps in flight before waiting for acks.
+const WINDOW_SIZE: usize = 8;
+// seconds to wait for a chunk's ack before retransmitting it.
+const CHUNK_TIMEOUT: u64 = 30;
+const MAX_RETRIES: u32 = 5;
+// milliseconds to wait before the first retry of a handshake or chunk, doubling with each one after.
+const RETRY_BACKOFF: u64 = 1000;
+const MAX_BACKOFF: u64 = 60000;
+// seconds the sender waits for the receiver to answer its size offer.
+const HANDSHAKE_TIMEOUT: u64 = 5;
+// milliseconds between a sender's heartbeats, and seconds it waits for each to be answered.
+const HEARTBEAT_INTERVAL: u64 = 10000;
+const HEARTBEAT_TIMEOUT: u64 = 30;
+// a sender whose receiver misses this many heartbeats in a row gives up.
+const MAX_MISSED_HEARTBEATS: u32 = 3;
+// a sender we haven't heard from in this long has its ranges handed to the others,
+// and a receiver that hasn't heard from any sender in this long gives up.
+const PEER_TIMEOUT: Duration = Duration::from_secs(60);
+// milliseconds between the receiver's checks for stalled senders.
+const PEER_CHECK_INTERVAL: u64 = 10000;
+// how often we measure our transfer rate, and a sender reports its progress.
+const RATE_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+// longest side of a thumbnail, in pixels.
+const THUMBNAIL_SIZE: u32 = 256;
+// hidden folder in the files drive where receivers keep track of what they have.
+const TRANSFERS_DIR: &str = ".transfers";
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum WorkerRequest {
     Initialize {
         name: String,
         target_worker: Option<Address>,
+        resume: bool,
+        // receivers: the content hash every sender must match. senders: the hash of our file, if already known.
+        hash: Option<String>,
+        settings: TransferSettings,
     },
     Chunk {
         name: String,
         offset: u64,
         length: u64,
+        // hash and length are of the bytes before encoding.
+        hash: String,
+        codec: Codec,
+    },
+    Size {
+        size: u64,
+        hash: String,
+        // codecs the sender can compress with, in order of preference.
+        codecs: Vec<Codec>,
     },
-    Size(u64),
+    // receiver handing a sender a new set of ranges to send.
+    Assign(Ranges),
+    // sender has had every byte of its share acked. carries the whole file's size and
+    // digest, so the receiver can settle the transfer without waiting on another chunk.
+    Finished { size: u64, hash: String },
+    // receiver is finished and the sender can exit. verified says whether the file checked out.
+    Done { verified: bool },
+    // stop the transfer. from the main process this also stops the peer(s).
+    Cancel { delete_partial: bool },
+    // hold the transfer where it is, or pick it back up. forwarded to peers like Cancel.
+    Pause,
+    Resume,
+    // main process setting a sender's upload cap in bytes/s, None lifts it.
+    SetRateLimit(Option<u64>),
+    // sender checking the receiver is still there, and letting it know we are.
+    Heartbeat,
+    // main process passing on a FileHash request: hash our file at path and answer it.
+    // further requests for the same path are passed on to us too, and get the same answer.
+    Hash { path: String },
+    // main process: nothing else is waiting on our hash, we can go.
+    Exit,
+    // main process passing on a request for a thumbnail of the image at path: make it,
+    // keep it at cached, and answer. http says it's our http server asking rather than a peer.
+    Thumbnail { path: String, cached: String, http: bool },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum WorkerResponse {
+    // receiver's answer to Size: the byte ranges this sender should send,
+    // and the codec it picked from the ones offered.
+    Assigned { ranges: Ranges, codec: Codec },
+    // receiver's answer to Chunk, ok = false if it failed its hash check.
+    Ack { offset: u64, ok: bool },
+    Heartbeat,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum TransferRequest {
-    Progress { name: String, progress: u64 },
+    TransferEvent(TransferEvent),
+    // the hash of our file at path, for the main process to keep. None if it couldn't be read.
+    Hashed { path: String, content: Option<ContentId> },
+}
+
+/// our answer to a FileHash or Thumbnail request the main process passed on to us.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum TransferResponse {
+    FileHash(Option<ContentId>),
+    // whether there's a thumbnail in the blob.
+    Thumbnail(bool),
+}
+
+/// identifies a file by what's in it rather than where it is.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContentId {
+    pub size: u64,
+    pub hash: String,
+}
+
+/// something that happened to a transfer, reported to the main process by the worker on that end.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TransferEvent {
+    // the receiving worker's address, the same on both ends.
+    pub id: String,
+    pub name: String,
+    pub direction: Direction,
+    pub kind: TransferEventKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Send,
+    Receive,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum TransferEventKind {
+    // waiting for a worker, only the main process reports this.
+    Queued,
+    Started { size: u64, hash: String },
+    Progress {
+        size: u64,
+        // percent done. a sender counts only its own share.
+        progress: u64,
+        // bytes/s of file content, and seconds left at that rate.
+        rate: u64,
+        eta: Option<u64>,
+        stats: TransferStats,
+    },
+    Paused,
+    Resumed,
+    // receiver has everything and is checking it against the hash.
+    Verifying,
+    Completed,
+    // we gave up on the transfer. a receiver keeps what it has, so it can be resumed,
+    // unless it failed verification.
+    Failed { reason: String },
+    Cancelled,
+    // kino_files kept the file it already had, no worker ever ran.
+    Skipped,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TransferSettings {
+    pub min_chunk_size: u64,
+    pub max_chunk_size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TransferStats {
+    pub codec: Codec,
+    // bytes written to the file (acked, for a sender) vs bytes that went over the wire for them.
+    pub bytes: u64,
+    pub wire_bytes: u64,
+}
+
+/// what a timer we set was for, carried in its context.
+#[derive(Serialize, Deserialize, Debug)]
+enum TimerKind {
+    // receiver checking for stalled senders.
+    PeerCheck,
+    // sender checking the receiver is still there.
+    Heartbeat,
+    // sender waiting out its upload cap.
+    Throttle,
+    // sender backing off before resending the chunk at this offset.
+    Retry(u64),
+}
+
+#[derive(Default)]
+struct WorkerState {
+    // the receiving worker's address, shared by everyone on the transfer.
+    id: String,
+    name: String,
+    file: Option<File>,
+    codec: Codec,
+    paused: bool,
+    wire_bytes: u64,
+    // content bytes moved since rate_since, and the rate measured before that.
+    rate_bytes: u64,
+    rate_since: Option<Instant>,
+    rate: u64,
+    // receiver side
+    size: Option<u64>,
+    hash: Option<String>,
+    received: Ranges,
+    ranges_path: String,
+    // where the file goes once it's verified, it's staged under TRANSFERS_DIR until then.
+    final_path: String,
+    senders: Vec<Address>,
+    // what's still expected from each sender, and when we last heard from it.
+    assignments: HashMap<Address, Ranges>,
+    last_seen: HashMap<Address, Instant>,
+    // when we last heard from any sender, none until the first one sends its Size.
+    last_heard: Option<Instant>,
+    resends: HashMap<u64, u32>,
+    // sender side
+    target: Option<Address>,
+    to_send: Ranges,
+    in_flight: HashMap<u64, InFlight>,
+    settings: TransferSettings,
+    chunk_size: u64,
+    // smoothed ack round trip and per-chunk throughput in bytes/s.
+    rtt: Duration,
+    throughput: f64,
+    throughput_at_growth: f64,
+    throttle: Throttle,
+    acked: u64,
+    missed_heartbeats: u32,
+    // we've told the receiver our share is done, and it hasn't given us more since.
+    finished: bool,
+    // hashing side: the path we were asked to hash, and what we made of it.
+    hashed: Option<(String, Option<ContentId>)>,
+}
+
+struct InFlight {
+    length: u64,
+    attempts: u32,
+    sent_at: Instant,
+}
+
+fn set_timer(ms: u64, kind: TimerKind) -> anyhow::Result<()> {
+    timer::set_timer(ms, Some(serde_json::to_vec(&kind)?));
+    Ok(())
+}
+
+/// milliseconds to wait before retry number attempt + 1.
+fn backoff(attempt: u32) -> u64 {
+    (RETRY_BACKOFF << attempt.min(16)).min(MAX_BACKOFF)
+}
+
+fn hash_bytes(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn read_chunk(file: &mut File, offset: u64, length: u64) -> anyhow::Result<Vec<u8>> {
+    let mut buffer = vec![0; length as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_at(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn hash_file(file: &mut File, size: u64) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut offset = 0;
+    while offset < size {
+        let length = CHUNK_SIZE.min(size - offset);
+        hasher.update(&read_chunk(file, offset, length)?);
+        offset += length;
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn load_ranges(path: &str) -> Option<Ranges> {
+    let bytes = open_file(path, false, None).ok()?.read().ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn save_ranges(path: &str, ranges: &Ranges) -> anyhow::Result<()> {
+    create_file(path, None)?.write(&serde_json::to_vec(ranges)?)?;
+    Ok(())
+}
+
+/// move a finished file from staging into place, replacing whatever's there.
+fn move_into_place(from: &str, to: &str) -> anyhow::Result<()> {
+    let resp = Request::new()
+        .target(("our", "vfs", "distro", "sys"))
+        .body(serde_json::to_vec(&VfsRequest {
+            path: from.to_string(),
+            action: VfsAction::Rename {
+                new_path: to.to_string(),
+            },
+        })?)
+        .send_and_await_response(5)??;
+    match serde_json::from_slice::<VfsResponse>(resp.body())? {
+        VfsResponse::Ok => Ok(()),
+        VfsResponse::Err(e) => Err(anyhow::anyhow!("kino_files: couldn't move {} to {}: {:?}", from, to, e)),
+        _ => Err(anyhow::anyhow!("kino_files: unexpected response moving {} to {}", from, to)),
+    }
+}
+
+fn send_chunk(
+    file: &mut File,
+    name: &str,
+    target: &Address,
+    offset: u64,
+    length: u64,
+    codec: Codec,
+) -> anyhow::Result<u64> {
+    let buffer = read_chunk(file, offset, length)?;
+    let hash = hash_bytes(&buffer);
+
+    // only send it compressed if that actually saves something.
+    let (codec, buffer) = match codec {
+        Codec::None => (Codec::None, buffer),
+        codec => {
+            let compressed = compress(codec, &buffer)?;
+            if compressed.len() < buffer.len() {
+                (codec, compressed)
+            } else {
+                (Codec::None, buffer)
+            }
+        }
+    };
+    let wire_length = buffer.len() as u64;
+
+    Request::new()
+        .body(serde_json::to_vec(&WorkerRequest::Chunk {
+            name: name.to_string(),
+            offset,
+            length,
+            hash,
+            codec,
+        })?)
+        .target(target.clone())
+        .blob_bytes(buffer)
+        .expects_response(CHUNK_TIMEOUT)
+        .send()?;
+    Ok(wire_length)
+}
+
+/// top the window back up to WINDOW_SIZE chunks in flight.
+fn fill_window(state: &mut WorkerState) -> anyhow::Result<()> {
+    let (Some(file), Some(target)) = (state.file.as_mut(), state.target.as_ref()) else {
+        return Err(anyhow::anyhow!("kino_files: send error: not sending anything"));
+    };
+    // while paused, chunks already in flight finish but nothing new goes out.
+    if state.paused {
+        return Ok(());
+    }
+
+    while state.in_flight.len() < WINDOW_SIZE {
+        // over our upload cap, come back when there's allowance again.
+        if let Some(delay) = state.throttle.delay() {
+            if !state.throttle.waiting {
+                state.throttle.waiting = true;
+                set_timer(delay.as_millis() as u64 + 1, TimerKind::Throttle)?;
+            }
+            break;
+        }
+        let Some((offset, end)) = state.to_send.take(state.chunk_size) else {
+            break;
+        };
+        let length = end - offset;
+        let wire_length = send_chunk(file, &state.name, target, offset, length, state.codec)?;
+        state.throttle.spend(wire_length);
+        state.wire_bytes += wire_length;
+        state.in_flight.insert(
+            offset,
+            InFlight {
+                length,
+                attempts: 0,
+                sent_at: Instant::now(),
+            },
+        );
+    }
+    Ok(())
+}
+
+/// sender: once everything we were assigned is acked, say so, once per assignment.
+fn check_finished(state: &mut WorkerState) -> anyhow::Result<()> {
+    if state.finished || !state.to_send.is_empty() || !state.in_flight.is_empty() {
+        return Ok(());
+    }
+    let (Some(target), Some(size), Some(hash)) = (state.target.as_ref(), state.size, state.hash.as_ref()) else {
+        return Ok(());
+    };
+    Request::new()
+        .body(serde_json::to_vec(&WorkerRequest::Finished {
+            size,
+            hash: hash.clone(),
+        })?)
+        .target(target.clone())
+        .send()?;
+    state.finished = true;
+    Ok(())
+}
+
+/// grow chunks while acks come back quickly and bigger chunks keep moving more bytes,
+/// shrink them when acks get slow.
+fn adjust_chunk_size(state: &mut WorkerState, length: u64, rtt: Duration) {
+    let throughput = length as f64 / rtt.as_secs_f64().max(0.001);
+    if state.rtt.is_zero() {
+        state.rtt = rtt;
+        state.throughput = throughput;
+    } else {
+        state.rtt = (state.rtt * 7 + rtt) / 8;
+        state.throughput = (state.throughput * 7.0 + throughput) / 8.0;
+    }
+
+    let chunk_size = if state.rtt > TARGET_RTT {
+        state.chunk_size / 2
+    } else if state.rtt < TARGET_RTT / 2 && state.throughput >= state.throughput_at_growth {
+        state.throughput_at_growth = state.throughput;
+        state.chunk_size * 2
+    } else {
+        state.chunk_size
+    };
+    state.chunk_size = chunk_size
+        .max(state.settings.min_chunk_size)
+        .min(state.settings.max_chunk_size);
+}
+
+/// send an unacked chunk again. returns true if it's out of retries and we should give up.
+fn retransmit(state: &mut WorkerState, offset: u64) -> anyhow::Result<bool> {
+    let Some(chunk) = state.in_flight.get_mut(&offset) else {
+        return Ok(false);
+    };
+    chunk.attempts += 1;
+    if chunk.attempts > MAX_RETRIES {
+        return Ok(true);
+    }
+
+    let (Some(file), Some(target)) = (state.file.as_mut(), state.target.as_ref()) else {
+        return Err(anyhow::anyhow!("kino_files: send error: not sending anything"));
+    };
+    let wire_length = send_chunk(file, &state.name, target, offset, chunk.length, state.codec)?;
+    chunk.sent_at = Instant::now();
+    state.throttle.spend(wire_length);
+    state.wire_bytes += wire_length;
+    Ok(false)
+}
+
+fn handle_send_error(state: &mut WorkerState, send_error: SendError) -> anyhow::Result<bool> {
+    match serde_json::from_slice::<WorkerRequest>(send_error.message.body()) {
+        // a chunk timed out or its target is unreachable, back off and put it back on the wire.
+        Ok(WorkerRequest::Chunk { offset, .. }) => {
+            state.chunk_size = (state.chunk_size / 2).max(state.settings.min_chunk_size);
+            let attempts = state.in_flight.get(&offset).map_or(0, |chunk| chunk.attempts);
+            set_timer(backoff(attempts), TimerKind::Retry(offset))?;
+            Ok(false)
+        }
+        Ok(WorkerRequest::Heartbeat) => {
+            state.missed_heartbeats += 1;
+            Ok(false)
+        }
+        _ => Err(anyhow::anyhow!("kino_files: worker send error: {:?}", send_error.kind)),
+    }
+}
+
+/// offer the receiver our file, retrying with exponential backoff while it doesn't answer.
+fn handshake(target: &Address, size: u64, hash: &str) -> anyhow::Result<WorkerResponse> {
+    let mut attempt = 0;
+    loop {
+        let result = Request::new()
+            .body(serde_json::to_vec(&WorkerRequest::Size {
+                size,
+                hash: hash.to_string(),
+                codecs: SUPPORTED_CODECS.to_vec(),
+            })?)
+            .target(target.clone())
+            .send_and_await_response(HANDSHAKE_TIMEOUT)?;
+        match result {
+            Ok(resp) => return Ok(serde_json::from_slice(resp.body())?),
+            Err(send_error) if attempt < MAX_RETRIES => {
+                println!(
+                    "kino_files worker: no answer from {} ({:?}), retrying",
+                    target, send_error.kind
+                );
+            }
+            Err(send_error) => {
+                return Err(anyhow::anyhow!(
+                    "no answer from {} after {} tries ({:?})",
+                    target,
+                    attempt + 1,
+                    send_error.kind
+                ));
+            }
+        }
+        let _ = timer::set_and_await_timer(backoff(attempt));
+        attempt += 1;
+    }
+}
+
+/// a small png of the image at path, kept at cached until the image changes.
+fn thumbnail(path: &str, cached: &str) -> anyhow::Result<Vec<u8>> {
+    let image = image::load_from_memory(&open_file(path, false, None)?.read()?)?;
+    let mut png = Cursor::new(Vec::new());
+    image
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .write_to(&mut png, image::ImageFormat::Png)?;
+    let png = png.into_inner();
+    if let Some((dir, _)) = cached.rsplit_once('/') {
+        open_dir(dir, true, None)?;
+    }
+    create_file(cached, None)?.write(&png)?;
+    Ok(png)
+}
+
+/// the receiver has let us know it's there, or a sender has.
+fn heard_from(state: &mut WorkerState, source: &Address) {
+    state.last_seen.insert(source.clone(), Instant::now());
+    state.last_heard = Some(Instant::now());
+}
+
+/// give up on the transfer and tell the main process why.
+/// a receiver lets its senders go and keeps the partial file for resuming.
+fn fail(our: &Address, state: &mut WorkerState, reason: String) -> anyhow::Result<bool> {
+    println!("kino_files worker: {} failed: {}", state.name, reason);
+    if state.target.is_none() {
+        for sender in &state.senders {
+            let _ = Request::new()
+                .body(serde_json::to_vec(&WorkerRequest::Cancel {
+                    delete_partial: false,
+                })?)
+                .target(sender.clone())
+                .send();
+        }
+    }
+
+    emit(our, state, TransferEventKind::Failed { reason })?;
+    Ok(true)
+}
+
+/// receiver: if this sender is through its share, give it more if there's any.
+fn top_up(state: &mut WorkerState, sender: &Address) -> anyhow::Result<()> {
+    if !state.assignments.get(sender).is_some_and(|ranges| ranges.is_empty()) {
+        return Ok(());
+    }
+    let assignment = next_assignment(state, sender)?;
+    if !assignment.is_empty() {
+        Request::new()
+            .body(serde_json::to_vec(&WorkerRequest::Assign(assignment))?)
+            .target(sender.clone())
+            .send()?;
+    }
+    Ok(())
+}
+
+/// find more work for a sender that's run out: ranges nobody is on,
+/// or else the back half of whoever has the most left.
+fn next_assignment(state: &mut WorkerState, sender: &Address) -> anyhow::Result<Ranges> {
+    let Some(size) = state.size else {
+        return Ok(Ranges::default());
+    };
+
+    let mut unassigned = state.received.missing(size);
+    for ranges in state.assignments.values() {
+        unassigned.subtract(ranges);
+    }
+
+    let assignment = if !unassigned.is_empty() {
+        unassigned
+    } else {
+        let victim = state
+            .assignments
+            .iter()
+            .filter(|(address, ranges)| *address != sender && ranges.len() > 2 * CHUNK_SIZE)
+            .max_by_key(|(_, ranges)| ranges.len())
+            .map(|(address, _)| address.clone());
+        let Some(victim) = victim else {
+            return Ok(Ranges::default());
+        };
+        let Some(victim_ranges) = state.assignments.get_mut(&victim) else {
+            return Ok(Ranges::default());
+        };
+        let stolen = victim_ranges.split_off_half();
+        Request::new()
+            .body(serde_json::to_vec(&WorkerRequest::Assign(victim_ranges.clone()))?)
+            .target(victim)
+            .send()?;
+        stolen
+    };
+
+    state
+        .assignments
+        .entry(sender.clone())
+        .or_default()
+        .extend(&assignment);
+    Ok(assignment)
+}
+
+/// drop senders that have gone quiet and spread their ranges over the idle ones.
+fn check_senders(state: &mut WorkerState) -> anyhow::Result<()> {
+    let stalled: Vec<Address> = state
+        .assignments
+        .iter()
+        .filter(|(address, ranges)| {
+            !ranges.is_empty()
+                && state
+                    .last_seen
+                    .get(*address)
+                    .is_none_or(|seen| seen.elapsed() > PEER_TIMEOUT)
+        })
+        .map(|(address, _)| address.clone())
+        .collect();
+    if stalled.is_empty() {
+        return Ok(());
+    }
+
+    for address in &stalled {
+        println!(
+            "kino_files worker: {} stalled, handing its ranges to other senders",
+            address
+        );
+        state.assignments.remove(address);
+        let _ = Request::new()
+            .body(serde_json::to_vec(&WorkerRequest::Cancel {
+                delete_partial: false,
+            })?)
+            .target(address.clone())
+            .send();
+    }
+    reassign_idle(state)
+}
+
+/// give senders with nothing left to do whatever is unassigned or worth splitting.
+fn reassign_idle(state: &mut WorkerState) -> anyhow::Result<()> {
+    let idle: Vec<Address> = state
+        .assignments
+        .iter()
+        .filter(|(_, ranges)| ranges.is_empty())
+        .map(|(address, _)| address.clone())
+        .collect();
+    for address in idle {
+        let assignment = next_assignment(state, &address)?;
+        if !assignment.is_empty() {
+            Request::new()
+                .body(serde_json::to_vec(&WorkerRequest::Assign(assignment))?)
+                .target(address)
+                .send()?;
+        }
+    }
+    Ok(())
+}
+
+/// stop the transfer. if the main process asked, the peers are told to stop too;
+/// if one sender of several dropped out, the others just pick up its share.
+fn cancel(
+    our: &Address,
+    state: &mut WorkerState,
+    source: &Address,
+    delete_partial: bool,
+) -> anyhow::Result<bool> {
+    let receiving = state.target.is_none();
+    let from_main = *source == main_app(our)?;
+    // a receiver the main process spawned but never set going, e.g. a swarm download
+    // that never heard what it's after. it goes by our address all the same.
+    if state.id.is_empty() {
+        state.id = our.to_string();
+    }
+
+    if receiving && !from_main && state.senders.len() > 1 {
+        println!("kino_files worker: {} cancelled, carrying on with the others", source);
+        state.senders.retain(|sender| sender != source);
+        state.assignments.remove(source);
+        reassign_idle(state)?;
+        return Ok(false);
+    }
+
+    if from_main {
+        let peers = match state.target {
+            Some(ref target) => vec![target.clone()],
+            None => state.senders.clone(),
+        };
+        for peer in peers {
+            Request::new()
+                .body(serde_json::to_vec(&WorkerRequest::Cancel {
+                    delete_partial: false,
+                })?)
+                .target(peer)
+                .send()?;
+        }
+    }
+
+    if receiving && delete_partial {
+        if let Some(file) = state.file.take() {
+            remove_file(&file.path, None)?;
+        }
+        let _ = remove_file(&state.ranges_path, None);
+    }
+
+    println!("kino_files worker: {} cancelled", state.name);
+    emit(our, state, TransferEventKind::Cancelled)?;
+    Ok(true)
+}
+
+/// pause or resume the transfer, everything received and left to send stays as is.
+fn set_paused(
+    our: &Address,
+    state: &mut WorkerState,
+    source: &Address,
+    paused: bool,
+) -> anyhow::Result<()> {
+    if *source == main_app(our)? {
+        let peers = match state.target {
+            Some(ref target) => vec![target.clone()],
+            None => state.senders.clone(),
+        };
+        let request = if paused {
+            WorkerRequest::Pause
+        } else {
+            WorkerRequest::Resume
+        };
+        for peer in peers {
+            Request::new()
+                .body(serde_json::to_vec(&request)?)
+                .target(peer)
+                .send()?;
+        }
+    }
+    if state.paused == paused {
+        return Ok(());
+    }
+    state.paused = paused;
+    println!(
+        "kino_files worker: {} {}",
+        state.name,
+        if paused { "paused" } else { "resumed" }
+    );
+
+    emit(
+        our,
+        state,
+        if paused {
+            TransferEventKind::Paused
+        } else {
+            TransferEventKind::Resumed
+        },
+    )?;
+
+    if !paused {
+        if state.target.is_some() {
+            fill_window(state)?;
+        } else {
+            // senders were quiet because we asked them to be, don't count it against them.
+            for seen in state.last_seen.values_mut() {
+                *seen = Instant::now();
+            }
+            if state.last_heard.is_some() {
+                state.last_heard = Some(Instant::now());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// count bytes moved, and once every RATE_REPORT_INTERVAL turn them into a new rate.
+/// returns whether the rate was updated.
+fn update_rate(state: &mut WorkerState, bytes: u64) -> bool {
+    state.rate_bytes += bytes;
+    let since = *state.rate_since.get_or_insert_with(Instant::now);
+    let elapsed = since.elapsed();
+    if elapsed < RATE_REPORT_INTERVAL {
+        return false;
+    }
+    state.rate = (state.rate_bytes as f64 / elapsed.as_secs_f64()) as u64;
+    state.rate_bytes = 0;
+    state.rate_since = Some(Instant::now());
+    true
+}
+
+fn report_progress(our: &Address, state: &WorkerState) -> anyhow::Result<()> {
+    let size = state.size.unwrap_or(0);
+    let (bytes, remaining) = match state.target {
+        Some(_) => (
+            state.acked,
+            state.to_send.len() + state.in_flight.values().map(|chunk| chunk.length).sum::<u64>(),
+        ),
+        None => (state.received.len(), size.saturating_sub(state.received.len())),
+    };
+    let progress = match size {
+        0 => 100,
+        size => ((size - remaining.min(size)) as f64 / size as f64 * 100.0) as u64,
+    };
+    emit(
+        our,
+        state,
+        TransferEventKind::Progress {
+            size,
+            progress,
+            rate: state.rate,
+            eta: (state.rate > 0).then(|| remaining / state.rate),
+            stats: TransferStats {
+                codec: state.codec,
+                bytes,
+                wire_bytes: state.wire_bytes,
+            },
+        },
+    )
+}
+
+fn emit(our: &Address, state: &WorkerState, kind: TransferEventKind) -> anyhow::Result<()> {
+    let direction = match state.target {
+        Some(_) => Direction::Send,
+        None => Direction::Receive,
+    };
+    Request::new()
+        .body(serde_json::to_vec(&TransferRequest::TransferEvent(TransferEvent {
+            id: state.id.clone(),
+            name: state.name.clone(),
+            direction,
+            kind,
+        }))?)
+        .target(&main_app(our)?)
+        .send()?;
+    Ok(())
+}
+
+/// the main process of whichever package we were installed as part of.
+fn main_app(our: &Address) -> anyhow::Result<Address> {
+    Ok(Address {
+        node: our.node.clone(),
+        process: ProcessId::from_str(&format!("kino_files:{}", our.package_id()))?,
+    })
+}
+
+/// whether source is our main process, or a peer in this transfer.
+fn is_party(our: &Address, state: &WorkerState, source: &Address) -> anyhow::Result<bool> {
+    Ok(*source == main_app(our)?
+        || state.target.as_ref() == Some(source)
+        || state.senders.contains(source))
 }
 
 fn handle_message(
     our: &Address,
-    file: &mut Option<File>,
+    state: &mut WorkerState,
     files_dir: &Directory,
-    size: &mut Option<u64>,
 ) -> anyhow::Result<bool> {
-    let message = await_message()?;
+    let message = match await_message() {
+        Ok(message) => message,
+        Err(send_error) => return handle_send_error(state, send_error),
+    };
 
     match message {
-        Message::Request { ref body, .. } => {
+        Message::Request {
+            ref source,
+            ref body,
+            ..
+        } => {
             let request = serde_json::from_slice::<WorkerRequest>(body)?;
 
+            // only the main process sets us going, once, and tells us what to do on its own behalf.
+            // only senders that have shaken hands send us data, and only the main process
+            // and the peers of this transfer may steer it.
+            let allowed = match request {
+                WorkerRequest::Initialize { .. } => *source == main_app(our)? && state.id.is_empty(),
+                WorkerRequest::SetRateLimit(_)
+                | WorkerRequest::Hash { .. }
+                | WorkerRequest::Exit
+                | WorkerRequest::Thumbnail { .. } => *source == main_app(our)?,
+                WorkerRequest::Chunk { .. } | WorkerRequest::Finished { .. } => state.senders.contains(source),
+                WorkerRequest::Assign(_)
+                | WorkerRequest::Done { .. }
+                | WorkerRequest::Cancel { .. }
+                | WorkerRequest::Pause
+                | WorkerRequest::Resume => is_party(our, state, source)?,
+                WorkerRequest::Size { .. } | WorkerRequest::Heartbeat => true,
+            };
+            if !allowed {
+                return Err(anyhow::anyhow!("kino_files: {:?} from {}", request, source));
+            }
+
             match request {
                 WorkerRequest::Initialize {
                     name,
                     target_worker,
+                    resume,
+                    hash,
+                    settings,
                 } => {
                     // initialize command from main process,
                     // sets up worker, matches on if it's a sender or receiver.
                     // target_worker = None, we are receiver, else sender.
 
-                    // open/create empty file in both cases.
-                    let mut active_file =
-                        open_file(&format!("{}/{}", files_dir.path, &name), true, None)?;
-
                     match target_worker {
                         Some(target_worker) => {
                             // we have a target, chunk the data, and send it.
-                            let size = active_file.metadata()?.len;
-                            let num_chunks = (size as f64 / CHUNK_SIZE as f64).ceil() as u64;
-
-                            // give the receiving worker a size request so it can track it's progress!
-                            Request::new()
-                                .body(serde_json::to_vec(&WorkerRequest::Size(size))?)
-                                .target(target_worker.clone())
-                                .send()?;
+                            state.id = target_worker.to_string();
+                            state.name = name.clone();
+                            state.target = Some(target_worker.clone());
 
-                            active_file.seek(SeekFrom::Start(0))?;
+                            // from here on every way out has to tell the main process, or it waits on us forever.
+                            let opened = open_file(&format!("{}/{}", files_dir.path, &name), true, None)
+                                .map_err(anyhow::Error::from)
+                                .and_then(|mut file| {
+                                    let size = file.metadata()?.len;
+                                    // the main process passes along a hash it has cached, only hash the file without one.
+                                    let hash = match hash {
+                                        Some(hash) => hash,
+                                        None => hash_file(&mut file, size)?,
+                                    };
+                                    Ok((file, size, hash))
+                                });
+                            let (active_file, size, hash) = match opened {
+                                Ok(opened) => opened,
+                                Err(e) => return fail(our, state, e.to_string()),
+                            };
 
-                            for i in 0..num_chunks {
-                                let offset = i * CHUNK_SIZE;
-                                let length = CHUNK_SIZE.min(size - offset);
+                            // give the receiving worker a size request so it can track it's progress,
+                            // it answers with the ranges it's missing so we only send those.
+                            let (to_send, codec) = match handshake(&target_worker, size, &hash) {
+                                Ok(WorkerResponse::Assigned { ranges, codec }) => (ranges, codec),
+                                Ok(_) => return fail(our, state, "unexpected handshake response".to_string()),
+                                Err(e) => return fail(our, state, e.to_string()),
+                            };
+                            if to_send.len() < size {
+                                println!(
+                                    "kino_files worker: sending {} of {} bytes of {}",
+                                    to_send.len(),
+                                    size,
+                                    name
+                                );
+                            }
 
-                                let mut buffer = vec![0; length as usize];
-                                active_file.read_at(&mut buffer)?;
+                            Response::new()
+                                .body(serde_json::to_vec(&"Started")?)
+                                .send()?;
 
-                                Request::new()
-                                    .body(serde_json::to_vec(&WorkerRequest::Chunk {
-                                        name: name.clone(),
-                                        offset,
-                                        length,
-                                    })?)
-                                    .target(target_worker.clone())
-                                    .blob_bytes(buffer)
-                                    .send()?;
-                            }
-                            Response::new().body(serde_json::to_vec(&"Done")?).send()?;
-                            return Ok(true);
+                            // from here on chunks go out as acks come back.
+                            state.file = Some(active_file);
+                            state.size = Some(size);
+                            state.hash = Some(hash.clone());
+                            state.to_send = to_send;
+                            state.codec = codec;
+                            state.chunk_size = CHUNK_SIZE
+                                .max(settings.min_chunk_size)
+                                .min(settings.max_chunk_size);
+                            state.settings = settings;
+                            state.rate_since = Some(Instant::now());
+                            emit(our, state, TransferEventKind::Started { size, hash: hash.clone() })?;
+                            set_timer(HEARTBEAT_INTERVAL, TimerKind::Heartbeat)?;
+                            fill_window(state)?;
+                            // an empty file, or one they already have, has nothing to send.
+                            check_finished(state)?;
                         }
                         None => {
-                            // waiting for response, store created empty file.
-                            *file = Some(active_file);
+                            // waiting for chunks. unless we're resuming a partial file,
+                            // start from an empty one. it's written out of sight, so nobody
+                            // takes a half-written file for a finished one.
+                            open_dir(&format!("{}/{}", files_dir.path, TRANSFERS_DIR), true, None)?;
+                            // keyed by a digest of the name, so no two names share a staging file.
+                            let staged = format!("{}/{}/{}", files_dir.path, TRANSFERS_DIR, hash_bytes(name.as_bytes()));
+                            let mut active_file = open_file(&format!("{}.part", staged), true, None)?;
+                            state.ranges_path = format!("{}.ranges", staged);
+                            state.final_path = format!("{}/{}", files_dir.path, &name);
+                            state.received = if resume {
+                                match load_ranges(&state.ranges_path) {
+                                    Some(ranges) => ranges,
+                                    // no record of what arrived, it was an in-order transfer that got cut off.
+                                    None => Ranges::new(0, active_file.metadata()?.len),
+                                }
+                            } else {
+                                active_file.set_len(0)?;
+                                Ranges::default()
+                            };
+                            state.id = our.to_string();
+                            state.name = name;
+                            state.file = Some(active_file);
+                            state.hash = hash;
+                            // a sender may spend a while hashing before it says hello,
+                            // so the clock on hearing from one starts with its first Size.
+                            set_timer(PEER_CHECK_INTERVAL, TimerKind::PeerCheck)?;
                             Response::new()
                                 .body(serde_json::to_vec(&"Started")?)
                                 .send()?;
@@ -109,9 +1010,11 @@ fn handle_message(
                     name,
                     offset,
                     length,
+                    hash,
+                    codec,
                 } => {
-                    let file = match file {
-                        Some(file) => file,
+                    let file = match state.file {
+                        Some(ref mut file) => file,
                         None => {
                             return Err(anyhow::anyhow!(
                                 "kino_files: receive error: no file initialized"
@@ -119,52 +1022,440 @@ fn handle_message(
                         }
                     };
 
-                    let bytes = match get_blob() {
+                    let wire_bytes = match get_blob() {
                         Some(blob) => blob.bytes,
                         None => {
                             return Err(anyhow::anyhow!("kino_files: receive error: no blob"));
                         }
                     };
+                    let wire_length = wire_bytes.len() as u64;
+
+                    // a chunk that won't even decode, or claims to run past the end of the file, counts as corrupted.
+                    let fits = state
+                        .size
+                        .is_some_and(|size| offset.checked_add(length).is_some_and(|end| end <= size));
+                    let bytes = match fits {
+                        true => decompress(codec, wire_bytes, length).unwrap_or_default(),
+                        false => vec![],
+                    };
+                    let ok = fits && bytes.len() as u64 == length && hash_bytes(&bytes) == hash;
+                    Response::new()
+                        .body(serde_json::to_vec(&WorkerResponse::Ack { offset, ok })?)
+                        .send()?;
+
+                    if !ok {
+                        let resends = state.resends.entry(offset).or_insert(0);
+                        *resends += 1;
+                        if *resends > MAX_RESENDS {
+                            println!(
+                                "kino_files worker: chunk at {} of {} kept failing its hash check",
+                                offset, name
+                            );
+                            return finish_receiving(our, state, false);
+                        }
+                        println!(
+                            "kino_files worker: chunk at {} of {} failed its hash check, asking for it again",
+                            offset, name
+                        );
+                        return Ok(false);
+                    }
+
+                    // chunks can arrive in any order and from several senders, write each
+                    // where it belongs. skip ones we already have, e.g. a retransmit whose first ack got lost.
+                    if !state.received.contains(offset, offset + length) {
+                        file.seek(SeekFrom::Start(offset))?;
+                        file.write_all(&bytes)?;
+                        state.received.insert(offset, offset + length);
+                        state.wire_bytes += wire_length;
+                        save_ranges(&state.ranges_path, &state.received)?;
+                        update_rate(state, length);
+                    }
+                    heard_from(state, source);
+                    for ranges in state.assignments.values_mut() {
+                        ranges.remove(offset, offset + length);
+                    }
 
-                    // file.seek(SeekFrom::Start(offset))?; seek not necessary if the sends come in order.
-                    file.write_all(&bytes)?;
                     // if sender has sent us a size, give a progress update to main transfer!
-                    if let Some(size) = size {
-                        let progress = ((offset + length) as f64 / *size as f64 * 100.0) as u64;
+                    if let Some(size) = state.size {
+                        report_progress(our, state)?;
+                        if state.received.is_complete(size) {
+                            return verify(our, state, size);
+                        }
+                    }
 
-                        // send update to main process
-                        let main_app = Address {
-                            node: our.node.clone(),
-                            process: ProcessId::from_str(
-                                "kino_files:kino_files:gloriainexcelsisdeo.os",
-                            )?,
-                        };
+                    top_up(state, source)?;
+                }
+                WorkerRequest::Size {
+                    size: incoming_size,
+                    hash,
+                    codecs,
+                } => {
+                    let file = match state.file {
+                        Some(ref mut file) => file,
+                        None => {
+                            return Err(anyhow::anyhow!(
+                                "kino_files: receive error: no file initialized"
+                            ));
+                        }
+                    };
 
+                    // in a swarm every sender has to be serving the same content.
+                    let matches = match (&state.hash, state.size) {
+                        (Some(expected), _) if *expected != hash => false,
+                        (_, Some(expected)) if expected != incoming_size => false,
+                        _ => true,
+                    };
+                    if !matches {
+                        println!(
+                            "kino_files worker: {} is serving different content, ignoring it",
+                            source
+                        );
+                        Response::new()
+                            .body(serde_json::to_vec(&WorkerResponse::Assigned {
+                                ranges: Ranges::default(),
+                                codec: Codec::None,
+                            })?)
+                            .send()?;
                         Request::new()
-                            .body(serde_json::to_vec(&TransferRequest::Progress {
-                                name,
-                                progress,
+                            .body(serde_json::to_vec(&WorkerRequest::Cancel {
+                                delete_partial: false,
                             })?)
-                            .target(&main_app)
+                            .target(source.clone())
                             .send()?;
+                        return Ok(false);
+                    }
 
-                        if progress >= 100 {
-                            return Ok(true);
+                    // if what we already have doesn't fit the file, it's stale, start over.
+                    if state.received.exceeds(incoming_size) {
+                        file.set_len(0)?;
+                        state.received = Ranges::default();
+                    }
+
+                    let started = state.size.is_none();
+                    let known = state.senders.contains(source);
+                    state.size = Some(incoming_size);
+                    state.hash = Some(hash);
+                    if !known {
+                        state.senders.push(source.clone());
+                    }
+                    heard_from(state, source);
+                    if started {
+                        emit(
+                            our,
+                            state,
+                            TransferEventKind::Started {
+                                size: incoming_size,
+                                hash: state.hash.clone().unwrap_or_default(),
+                            },
+                        )?;
+                    }
+
+                    // nothing left to fetch, the sender won't send any chunks.
+                    if state.received.is_complete(incoming_size) {
+                        Response::new()
+                            .body(serde_json::to_vec(&WorkerResponse::Assigned {
+                                ranges: Ranges::default(),
+                                codec: Codec::None,
+                            })?)
+                            .send()?;
+                        return verify(our, state, incoming_size);
+                    }
+
+                    // pick the sender's favourite codec that we also speak.
+                    let codec = codecs
+                        .into_iter()
+                        .find(|codec| SUPPORTED_CODECS.contains(codec))
+                        .unwrap_or_default();
+                    state.codec = codec;
+
+                    // a sender asking again didn't hear our first answer, its share is still the one we gave it.
+                    let assignment = match state.assignments.get(source) {
+                        Some(ranges) if known => ranges.clone(),
+                        _ => next_assignment(state, source)?,
+                    };
+                    Response::new()
+                        .body(serde_json::to_vec(&WorkerResponse::Assigned {
+                            ranges: assignment,
+                            codec,
+                        })?)
+                        .send()?;
+                }
+                WorkerRequest::Assign(ranges) => {
+                    // our share changed. chunks in flight stay in flight, the rest is replaced.
+                    let mut to_send = ranges;
+                    for (offset, chunk) in &state.in_flight {
+                        to_send.remove(*offset, offset + chunk.length);
+                    }
+                    if !to_send.is_empty() {
+                        state.finished = false;
+                    }
+                    state.to_send = to_send;
+                    fill_window(state)?;
+                    check_finished(state)?;
+                }
+                WorkerRequest::Finished { size, hash } => {
+                    if state.size != Some(size) || state.hash.as_ref() != Some(&hash) {
+                        println!(
+                            "kino_files worker: {} finished different content, ignoring it",
+                            source
+                        );
+                        return Ok(false);
+                    }
+                    heard_from(state, source);
+                    // whatever size the file is, even 0, having all of it settles the transfer.
+                    if state.received.is_complete(size) {
+                        return verify(our, state, size);
+                    }
+                    top_up(state, source)?;
+                }
+                WorkerRequest::Done { verified } => {
+                    emit(
+                        our,
+                        state,
+                        match verified {
+                            true => TransferEventKind::Completed,
+                            false => TransferEventKind::Failed {
+                                reason: "receiver's copy doesn't match its hash".to_string(),
+                            },
+                        },
+                    )?;
+                    return Ok(true);
+                }
+                WorkerRequest::Cancel { delete_partial } => {
+                    return cancel(our, state, source, delete_partial);
+                }
+                WorkerRequest::Pause => {
+                    set_paused(our, state, source, true)?;
+                }
+                WorkerRequest::Resume => {
+                    set_paused(our, state, source, false)?;
+                }
+                WorkerRequest::SetRateLimit(limit) => {
+                    state.throttle.set_limit(limit);
+                    // a pending throttle timer still fires, filling early is harmless.
+                    if state.target.is_some() {
+                        fill_window(state)?;
+                    }
+                }
+                WorkerRequest::Hash { path } => {
+                    let content = match state.hashed {
+                        Some((ref hashed, ref content)) if *hashed == path => content.clone(),
+                        _ => {
+                            let content = open_file(&path, false, None)
+                                .map_err(anyhow::Error::from)
+                                .and_then(|mut file| {
+                                    let size = file.metadata()?.len;
+                                    let hash = hash_file(&mut file, size)?;
+                                    Ok(ContentId { size, hash })
+                                })
+                                .map_err(|e| println!("kino_files worker: couldn't hash {}: {:?}", path, e))
+                                .ok();
+                            state.hashed = Some((path.clone(), content.clone()));
+                            Request::new()
+                                .body(serde_json::to_vec(&TransferRequest::Hashed {
+                                    path,
+                                    content: content.clone(),
+                                })?)
+                                .target(&main_app(our)?)
+                                .send()?;
+                            content
+                        }
+                    };
+                    // the request was passed on to us, so this goes to whoever asked for the hash.
+                    // we stay until the main process says nobody else is waiting on it.
+                    Response::new()
+                        .body(serde_json::to_vec(&TransferResponse::FileHash(content))?)
+                        .send()?;
+                }
+                WorkerRequest::Exit => return Ok(true),
+                WorkerRequest::Thumbnail { path, cached, http } => {
+                    let png = thumbnail(&path, &cached)
+                        .map_err(|e| println!("kino_files worker: no thumbnail for {}: {:?}", path, e))
+                        .ok();
+                    // the request was passed on to us, so this goes to whoever asked for the thumbnail.
+                    match (http, png) {
+                        (true, Some(png)) => {
+                            let mut headers = HashMap::new();
+                            headers.insert("Content-Type".to_string(), "image/png".to_string());
+                            send_response(StatusCode::OK, Some(headers), png);
+                        }
+                        (true, None) => send_response(StatusCode::NOT_FOUND, None, vec![]),
+                        (false, Some(png)) => {
+                            Response::new()
+                                .body(serde_json::to_vec(&TransferResponse::Thumbnail(true))?)
+                                .blob_bytes(png)
+                                .send()?;
+                        }
+                        (false, None) => {
+                            Response::new()
+                                .body(serde_json::to_vec(&TransferResponse::Thumbnail(false))?)
+                                .send()?;
                         }
                     }
+                    return Ok(true);
                 }
-                WorkerRequest::Size(incoming_size) => {
-                    *size = Some(incoming_size);
+                WorkerRequest::Heartbeat => {
+                    if state.senders.contains(source) {
+                        heard_from(state, source);
+                    }
+                    Response::new()
+                        .body(serde_json::to_vec(&WorkerResponse::Heartbeat)?)
+                        .send()?;
                 }
             }
         }
-        _ => {
-            println!("kino_files worker: got something else than request...");
+        Message::Response {
+            ref source,
+            ref body,
+            ref context,
+            ..
+        } => {
+            if source.process == ProcessId::from_str("timer:distro:sys")? {
+                let Some(kind) = context
+                    .as_ref()
+                    .and_then(|context| serde_json::from_slice::<TimerKind>(context).ok())
+                else {
+                    return Ok(false);
+                };
+                match kind {
+                    TimerKind::PeerCheck => {
+                        if !state.paused {
+                            check_senders(state)?;
+                            if state.last_heard.is_some_and(|heard| heard.elapsed() > PEER_TIMEOUT) {
+                                return fail(
+                                    our,
+                                    state,
+                                    format!("no sender heard from in {}s", PEER_TIMEOUT.as_secs()),
+                                );
+                            }
+                        }
+                        set_timer(PEER_CHECK_INTERVAL, TimerKind::PeerCheck)?;
+                    }
+                    TimerKind::Heartbeat => {
+                        if state.missed_heartbeats >= MAX_MISSED_HEARTBEATS {
+                            return fail(
+                                our,
+                                state,
+                                format!("receiver missed {} heartbeats", state.missed_heartbeats),
+                            );
+                        }
+                        if let Some(ref target) = state.target {
+                            Request::new()
+                                .body(serde_json::to_vec(&WorkerRequest::Heartbeat)?)
+                                .target(target.clone())
+                                .expects_response(HEARTBEAT_TIMEOUT)
+                                .send()?;
+                        }
+                        set_timer(HEARTBEAT_INTERVAL, TimerKind::Heartbeat)?;
+                    }
+                    TimerKind::Throttle => {
+                        state.throttle.waiting = false;
+                        fill_window(state)?;
+                    }
+                    TimerKind::Retry(offset) => {
+                        if retransmit(state, offset)? {
+                            return fail(
+                                our,
+                                state,
+                                format!("chunk at {} unacked after {} retries", offset, MAX_RETRIES),
+                            );
+                        }
+                    }
+                }
+                return Ok(false);
+            }
+            match serde_json::from_slice::<WorkerResponse>(body)? {
+                WorkerResponse::Ack { offset, ok: true } => {
+                    // once everything's acked we wait for the receiver to let us go,
+                    // it may still have more for us.
+                    let mut rate_updated = false;
+                    if let Some(chunk) = state.in_flight.remove(&offset) {
+                        // a retransmitted chunk's round trip says nothing reliable about the link.
+                        if chunk.attempts == 0 {
+                            adjust_chunk_size(state, chunk.length, chunk.sent_at.elapsed());
+                        }
+                        state.acked += chunk.length;
+                        rate_updated = update_rate(state, chunk.length);
+                    }
+                    fill_window(state)?;
+                    check_finished(state)?;
+                    if rate_updated {
+                        report_progress(our, state)?;
+                    }
+                }
+                WorkerResponse::Ack { offset, ok: false } => {
+                    if retransmit(state, offset)? {
+                        return fail(
+                            our,
+                            state,
+                            format!("chunk at {} rejected after {} retries", offset, MAX_RETRIES),
+                        );
+                    }
+                }
+                WorkerResponse::Heartbeat => {
+                    state.missed_heartbeats = 0;
+                }
+                WorkerResponse::Assigned { .. } => {}
+            }
         }
     }
     Ok(false)
 }
 
+/// receiver has every byte: check them against the hash the senders gave us.
+fn verify(our: &Address, state: &mut WorkerState, size: u64) -> anyhow::Result<bool> {
+    emit(our, state, TransferEventKind::Verifying)?;
+    let Some(file) = state.file.as_mut() else {
+        return Err(anyhow::anyhow!("kino_files: receive error: no file initialized"));
+    };
+    let file_hash = hash_file(file, size)?;
+    let verified = state.hash.as_ref() == Some(&file_hash);
+    finish_receiving(our, state, verified)
+}
+
+/// receiver is done: let the senders go, move the file into place if it matches
+/// what was sent and drop it if it doesn't, and report the result to the main process.
+fn finish_receiving(our: &Address, state: &mut WorkerState, verified: bool) -> anyhow::Result<bool> {
+    for sender in &state.senders {
+        Request::new()
+            .body(serde_json::to_vec(&WorkerRequest::Done { verified })?)
+            .target(sender.clone())
+            .send()?;
+    }
+    let _ = remove_file(&state.ranges_path, None);
+
+    if verified {
+        if let Some(file) = state.file.take() {
+            if let Err(e) = move_into_place(&file.path, &state.final_path) {
+                emit(our, state, TransferEventKind::Failed { reason: e.to_string() })?;
+                return Ok(true);
+            }
+        }
+    }
+
+    emit(
+        our,
+        state,
+        match verified {
+            true => TransferEventKind::Completed,
+            false => TransferEventKind::Failed {
+                reason: "content doesn't match its hash".to_string(),
+            },
+        },
+    )?;
+
+    if !verified {
+        if let Some(file) = state.file.take() {
+            println!(
+                "kino_files worker: {} failed verification, removing it",
+                state.name
+            );
+            remove_file(&file.path, None)?;
+        }
+    }
+    Ok(true)
+}
+
 struct Component;
 impl Guest for Component {
     fn init(our: String) {
@@ -176,11 +1467,10 @@ impl Guest for Component {
         let drive_path = format!("{}/files", our.package_id());
         let files_dir = open_dir(&drive_path, false, None).unwrap();
 
-        let mut file: Option<File> = None;
-        let mut size: Option<u64> = None;
+        let mut state = WorkerState::default();
 
         loop {
-            match handle_message(&our, &mut file, &files_dir, &mut size) {
+            match handle_message(&our, &mut state, &files_dir) {
                 Ok(exit) => {
                     if exit {
                         println!(
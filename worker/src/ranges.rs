@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+
+/// sorted, non-overlapping byte ranges [start, end) of a file.
+/// the receiver uses it to track what it has, the sender to track what's left to send.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Ranges(Vec<(u64, u64)>);
+
+impl Ranges {
+    pub fn new(start: u64, end: u64) -> Self {
+        let mut ranges = Ranges::default();
+        ranges.insert(start, end);
+        ranges
+    }
+
+    pub fn insert(&mut self, mut start: u64, mut end: u64) {
+        if start >= end {
+            return;
+        }
+        let mut merged = Vec::with_capacity(self.0.len() + 1);
+        let mut placed = false;
+        for &(s, e) in &self.0 {
+            if e < start {
+                merged.push((s, e));
+            } else if end < s {
+                if !placed {
+                    merged.push((start, end));
+                    placed = true;
+                }
+                merged.push((s, e));
+            } else {
+                // overlapping or touching, fold it into the new range.
+                start = start.min(s);
+                end = end.max(e);
+            }
+        }
+        if !placed {
+            merged.push((start, end));
+        }
+        self.0 = merged;
+    }
+
+    pub fn remove(&mut self, start: u64, end: u64) {
+        if start >= end {
+            return;
+        }
+        let mut kept = Vec::with_capacity(self.0.len() + 1);
+        for &(s, e) in &self.0 {
+            if e <= start || end <= s {
+                kept.push((s, e));
+                continue;
+            }
+            if s < start {
+                kept.push((s, start));
+            }
+            if end < e {
+                kept.push((end, e));
+            }
+        }
+        self.0 = kept;
+    }
+
+    pub fn extend(&mut self, other: &Ranges) {
+        for &(s, e) in &other.0 {
+            self.insert(s, e);
+        }
+    }
+
+    pub fn subtract(&mut self, other: &Ranges) {
+        for &(s, e) in &other.0 {
+            self.remove(s, e);
+        }
+    }
+
+    pub fn contains(&self, start: u64, end: u64) -> bool {
+        self.0.iter().any(|&(s, e)| s <= start && end <= e)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// total number of bytes covered.
+    pub fn len(&self) -> u64 {
+        self.0.iter().map(|(s, e)| e - s).sum()
+    }
+
+    /// the gaps in [0, size) that aren't covered yet.
+    pub fn missing(&self, size: u64) -> Ranges {
+        let mut missing = Ranges::default();
+        let mut cursor = 0;
+        for &(s, e) in &self.0 {
+            if s >= size {
+                break;
+            }
+            missing.insert(cursor, s);
+            cursor = e;
+        }
+        missing.insert(cursor, size);
+        missing
+    }
+
+    pub fn is_complete(&self, size: u64) -> bool {
+        self.missing(size).is_empty()
+    }
+
+    /// pop up to max_len bytes off the front.
+    pub fn take(&mut self, max_len: u64) -> Option<(u64, u64)> {
+        let first = self.0.first_mut()?;
+        let start = first.0;
+        let end = first.1.min(start + max_len);
+        if end == first.1 {
+            self.0.remove(0);
+        } else {
+            first.0 = end;
+        }
+        Some((start, end))
+    }
+
+    /// keep the front half of the bytes, hand back the rest.
+    pub fn split_off_half(&mut self) -> Ranges {
+        let mut keep = self.len() / 2;
+        let mut front = Ranges::default();
+        let mut back = Ranges::default();
+        for &(s, e) in &self.0 {
+            if keep >= e - s {
+                front.insert(s, e);
+                keep -= e - s;
+            } else {
+                front.insert(s, s + keep);
+                back.insert(s + keep, e);
+                keep = 0;
+            }
+        }
+        *self = front;
+        back
+    }
+
+    /// whether anything reaches past size.
+    pub fn exceeds(&self, size: u64) -> bool {
+        self.0.last().is_some_and(|&(_, e)| e > size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_merges_touching_ranges() {
+        let mut ranges = Ranges::new(0, 10);
+        ranges.insert(10, 20);
+        assert_eq!(ranges, Ranges(vec![(0, 20)]));
+    }
+
+    #[test]
+    fn insert_merges_overlapping_ranges() {
+        let mut ranges = Ranges::new(0, 10);
+        ranges.insert(20, 30);
+        ranges.insert(5, 25);
+        assert_eq!(ranges, Ranges(vec![(0, 30)]));
+    }
+
+    #[test]
+    fn insert_keeps_disjoint_ranges_sorted() {
+        let mut ranges = Ranges::new(20, 30);
+        ranges.insert(0, 10);
+        ranges.insert(40, 40);
+        assert_eq!(ranges, Ranges(vec![(0, 10), (20, 30)]));
+    }
+
+    #[test]
+    fn remove_splits_a_range() {
+        let mut ranges = Ranges::new(0, 30);
+        ranges.remove(10, 20);
+        assert_eq!(ranges, Ranges(vec![(0, 10), (20, 30)]));
+        assert_eq!(ranges.len(), 20);
+    }
+
+    #[test]
+    fn missing_stops_at_size() {
+        let ranges = Ranges(vec![(0, 10), (20, 40)]);
+        assert_eq!(ranges.missing(30), Ranges(vec![(10, 20)]));
+        assert_eq!(ranges.missing(50), Ranges(vec![(10, 20), (40, 50)]));
+        assert!(!ranges.is_complete(30));
+        assert!(Ranges::new(0, 40).is_complete(30));
+        assert!(Ranges::default().is_complete(0));
+    }
+
+    #[test]
+    fn split_off_half_across_ranges() {
+        let mut ranges = Ranges(vec![(0, 10), (20, 26)]);
+        let back = ranges.split_off_half();
+        assert_eq!(ranges, Ranges(vec![(0, 8)]));
+        assert_eq!(back, Ranges(vec![(8, 10), (20, 26)]));
+    }
+
+    #[test]
+    fn take_pops_off_the_front() {
+        let mut ranges = Ranges(vec![(0, 10), (20, 30)]);
+        assert_eq!(ranges.take(4), Some((0, 4)));
+        assert_eq!(ranges.take(10), Some((4, 10)));
+        assert_eq!(ranges, Ranges(vec![(20, 30)]));
+        assert!(!ranges.exceeds(30));
+        assert!(ranges.exceeds(29));
+    }
+}
@@ -0,0 +1,43 @@
+use std::time::{Duration, Instant};
+
+/// token bucket for the sender's upload cap. a chunk may overdraw it,
+/// after which sending waits for it to refill.
+#[derive(Debug, Default)]
+pub struct Throttle {
+    // bytes per second, None is unlimited.
+    limit: Option<u64>,
+    allowance: f64,
+    updated: Option<Instant>,
+    // a timer is already set to pick sending back up.
+    pub waiting: bool,
+}
+
+impl Throttle {
+    pub fn set_limit(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+        self.allowance = 0.0;
+        self.updated = None;
+    }
+
+    /// how long until we may send again, None if we can now.
+    pub fn delay(&mut self) -> Option<Duration> {
+        let limit = self.limit? as f64;
+        let now = Instant::now();
+        if let Some(updated) = self.updated {
+            // save up at most a second's worth, so a quiet transfer can't burst.
+            self.allowance = (self.allowance + (now - updated).as_secs_f64() * limit).min(limit);
+        }
+        self.updated = Some(now);
+        if self.allowance >= 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(-self.allowance / limit))
+        }
+    }
+
+    pub fn spend(&mut self, bytes: u64) {
+        if self.limit.is_some() {
+            self.allowance -= bytes as f64;
+        }
+    }
+}